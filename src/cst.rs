@@ -0,0 +1,240 @@
+use crate::parser::{Expr, Loc, ParseError, Parser, Span};
+use std::fmt;
+
+/// A lossless concrete syntax tree. Unlike `Expr`, every byte of the source
+/// belongs to exactly one token, so printing the tree gives back the source
+/// it was parsed from, including whitespace, brackets and separators.
+///
+/// ```
+/// use cayatex::cst::SyntaxTree;
+///
+/// let source = "[ bold  x ] {theorem  label=a |\n body}";
+/// assert_eq!(SyntaxTree::parse(source).to_string(), source);
+/// ```
+#[derive(Debug)]
+pub struct SyntaxTree {
+    source: String,
+    root: Node,
+    errors: Vec<Loc<ParseError>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeKind {
+    Document,
+    Inline,
+    Block,
+    Math,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    LBracket,
+    RBracket,
+    LBrace,
+    RBrace,
+    Pipe,
+    Quote,
+    Dollar,
+    Whitespace,
+    Name,
+    Arg,
+    Text,
+    /// The body of a raw element or the TeX of a math element
+    Raw,
+    /// Source the parser couldn't make sense of
+    Error,
+}
+
+#[derive(Debug)]
+pub struct Node {
+    kind: NodeKind,
+    range: Span,
+    children: Vec<Child>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Token {
+    kind: TokenKind,
+    range: Span,
+}
+
+#[derive(Debug)]
+pub enum Child {
+    Node(Node),
+    Token(Token),
+}
+
+impl SyntaxTree {
+    /// Parses `source`, recovering from errors. The tree covers the whole
+    /// source even when there are errors.
+    pub fn parse<T: Into<String>>(source: T) -> Self {
+        let source = source.into();
+        let (exprs, errors) = Parser::new(&source).parse_document_recovering();
+        let parts = exprs.iter().map(|expr| build_part(&source, expr)).collect();
+        let root = build_node(&source, NodeKind::Document, 0..source.len(), parts);
+
+        SyntaxTree {
+            source,
+            root,
+            errors,
+        }
+    }
+
+    pub fn root(&self) -> &Node {
+        &self.root
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn errors(&self) -> &[Loc<ParseError>] {
+        &self.errors
+    }
+
+    pub fn text(&self, token: &Token) -> &str {
+        &self.source[token.range.clone()]
+    }
+
+    /// All tokens in source order
+    pub fn tokens(&self) -> Vec<&Token> {
+        let mut tokens = Vec::new();
+        collect_tokens(&self.root, &mut tokens);
+        tokens
+    }
+}
+
+impl fmt::Display for SyntaxTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for token in self.tokens() {
+            f.write_str(self.text(token))?;
+        }
+        Ok(())
+    }
+}
+
+impl Node {
+    pub fn kind(&self) -> NodeKind {
+        self.kind
+    }
+
+    pub fn range(&self) -> Span {
+        self.range.clone()
+    }
+
+    pub fn children(&self) -> &[Child] {
+        &self.children
+    }
+}
+
+impl Token {
+    pub fn kind(&self) -> TokenKind {
+        self.kind
+    }
+
+    pub fn range(&self) -> Span {
+        self.range.clone()
+    }
+}
+
+fn collect_tokens<'a>(node: &'a Node, tokens: &mut Vec<&'a Token>) {
+    for child in &node.children {
+        match child {
+            Child::Node(node) => collect_tokens(node, tokens),
+            Child::Token(token) => tokens.push(token),
+        }
+    }
+}
+
+fn token(kind: TokenKind, range: Span) -> Child {
+    Child::Token(Token { kind, range })
+}
+
+fn build_part(source: &str, expr: &Loc<Expr>) -> Child {
+    let (kind, name, args, body) = match expr.inner() {
+        Expr::Inline { name, args, body } => (NodeKind::Inline, name, args, body),
+        Expr::Block { name, args, body } => (NodeKind::Block, name, args, body),
+        Expr::Text(span) => return token(TokenKind::Text, span.clone()),
+        Expr::Raw(span) => return token(TokenKind::Raw, span.clone()),
+        Expr::Math(math) => {
+            let mut parts: Vec<_> = math
+                .name
+                .iter()
+                .map(|name| token(TokenKind::Name, name.clone()))
+                .collect();
+            parts.extend(
+                math.args
+                    .iter()
+                    .map(|arg| token(TokenKind::Arg, arg.clone())),
+            );
+            if !math.tex.is_empty() {
+                parts.push(token(TokenKind::Raw, math.tex.clone()));
+            }
+            return Child::Node(build_node(source, NodeKind::Math, expr.range(), parts));
+        }
+        Expr::Error => return token(TokenKind::Error, expr.range()),
+    };
+
+    let mut parts = vec![token(TokenKind::Name, name.clone())];
+    parts.extend(args.iter().map(|arg| token(TokenKind::Arg, arg.clone())));
+    parts.extend(body.iter().map(|expr| build_part(source, expr)));
+    Child::Node(build_node(source, kind, expr.range(), parts))
+}
+
+// The AST only knows about names, arguments and bodies, so the tokens in the
+// gaps between them are recovered from the source
+fn build_node(source: &str, kind: NodeKind, range: Span, parts: Vec<Child>) -> Node {
+    let mut children = Vec::new();
+    let mut idx = range.start;
+    for part in parts {
+        let part_range = match &part {
+            Child::Node(node) => node.range(),
+            Child::Token(token) => token.range(),
+        };
+        lex_gap(source, idx..part_range.start, &mut children);
+        idx = part_range.end;
+        children.push(part);
+    }
+    lex_gap(source, idx..range.end, &mut children);
+
+    Node {
+        kind,
+        range,
+        children,
+    }
+}
+
+fn lex_gap(source: &str, range: Span, children: &mut Vec<Child>) {
+    let mut chars = source[range.clone()].char_indices().peekable();
+    while let Some((offset, c)) = chars.next() {
+        let start = range.start + offset;
+        let kind = match c {
+            '[' => TokenKind::LBracket,
+            ']' => TokenKind::RBracket,
+            '{' => TokenKind::LBrace,
+            '}' => TokenKind::RBrace,
+            '|' => TokenKind::Pipe,
+            '"' => TokenKind::Quote,
+            '$' => TokenKind::Dollar,
+            _ => {
+                let is_whitespace = c.is_whitespace();
+                let mut end = start + c.len_utf8();
+                while let Some(&(offset, c)) = chars.peek() {
+                    if c.is_whitespace() != is_whitespace || "[]{}|\"$".contains(c) {
+                        break;
+                    }
+                    end = range.start + offset + c.len_utf8();
+                    chars.next();
+                }
+                let kind = if is_whitespace {
+                    TokenKind::Whitespace
+                } else {
+                    TokenKind::Error
+                };
+                children.push(token(kind, start..end));
+                continue;
+            }
+        };
+        children.push(token(kind, start..start + c.len_utf8()));
+    }
+}
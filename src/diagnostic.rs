@@ -0,0 +1,375 @@
+use crate::line_index::LineIndex;
+use crate::mathml::MathError;
+use crate::parser::{ElementKind, Loc, ParseError};
+use crate::refs::{ReferenceError, LABEL_SPECIAL_CHARS};
+use crate::schema::ValidationError;
+use std::fmt::Write;
+use std::ops::Range;
+use unicode_width::UnicodeWidthChar;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Range<usize>,
+    pub message: String,
+}
+
+/// An error ready to be shown to a user, in the style of rustc: the message,
+/// the offending source lines with the spans underlined, and any help notes.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub message: String,
+    pub primary: Label,
+    pub secondary: Vec<Label>,
+    pub help: Vec<String>,
+    pub fixes: Vec<Fix>,
+}
+
+/// A replacement of part of the source that fixes a diagnostic. Fixes are
+/// only attached when they're certain enough to apply without review.
+#[derive(Debug, Clone)]
+pub struct Fix {
+    pub span: Range<usize>,
+    pub replacement: String,
+}
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const TAB_WIDTH: usize = 4;
+
+impl Diagnostic {
+    pub fn new<T: Into<String>>(level: Level, message: T, primary: Label) -> Self {
+        Diagnostic {
+            level,
+            message: message.into(),
+            primary,
+            secondary: Vec::new(),
+            help: Vec::new(),
+            fixes: Vec::new(),
+        }
+    }
+
+    pub fn with_label<T: Into<String>>(mut self, span: Range<usize>, message: T) -> Self {
+        self.secondary.push(Label {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn with_help<T: Into<String>>(mut self, help: T) -> Self {
+        self.help.push(help.into());
+        self
+    }
+
+    pub fn with_fix<T: Into<String>>(mut self, span: Range<usize>, replacement: T) -> Self {
+        self.fixes.push(Fix {
+            span,
+            replacement: replacement.into(),
+        });
+        self
+    }
+
+    pub fn render(&self, file_name: &str, index: &LineIndex, color: bool) -> String {
+        let paint = |style: &'static str| if color { style } else { "" };
+        let reset = paint(RESET);
+        let (level, level_style) = match self.level {
+            Level::Error => ("error", paint(RED)),
+            Level::Warning => ("warning", paint(YELLOW)),
+        };
+        let gutter_style = paint(BLUE);
+
+        let mut labels: Vec<(&Label, bool)> = vec![(&self.primary, true)];
+        labels.extend(self.secondary.iter().map(|label| (label, false)));
+        labels.sort_by_key(|(label, _)| label.span.start);
+
+        let last_line = labels
+            .iter()
+            .map(|(label, _)| index.line_col(label.span.start).line)
+            .max()
+            .unwrap_or(1);
+        let gutter_width = last_line.to_string().len();
+        let blank_gutter = " ".repeat(gutter_width);
+
+        let mut out = String::new();
+        let start = index.line_col_chars(self.primary.span.start);
+        let _ = writeln!(
+            out,
+            "{}{}{}{}: {}{}",
+            level_style,
+            level,
+            reset,
+            paint(BOLD),
+            self.message,
+            reset
+        );
+        let _ = writeln!(
+            out,
+            "{}{}-->{} {}:{}:{}",
+            blank_gutter, gutter_style, reset, file_name, start.line, start.col
+        );
+        let _ = writeln!(out, "{} {}|{}", blank_gutter, gutter_style, reset);
+
+        let mut previous_line = None;
+        for (label, is_primary) in labels {
+            let line_col = index.line_col(label.span.start);
+            let line = index.line(line_col.line).unwrap_or("");
+            if previous_line != Some(line_col.line) {
+                if previous_line.is_some_and(|previous| previous + 1 < line_col.line) {
+                    let _ = writeln!(out, "{}...{}", gutter_style, reset);
+                }
+                let _ = writeln!(
+                    out,
+                    "{}{:>width$} |{} {}",
+                    gutter_style,
+                    line_col.line,
+                    reset,
+                    expand_tabs(line),
+                    width = gutter_width
+                );
+                previous_line = Some(line_col.line);
+            }
+
+            // Spans covering several lines are only underlined on the first
+            let line_offset = label.span.start - (line_col.col - 1);
+            let start_col = line_col.col - 1;
+            let end_col = (label.span.end.max(label.span.start) - line_offset).min(line.len());
+            let padding = display_width(&line[..start_col.min(line.len())]);
+            let underline_width = if end_col > start_col {
+                display_width(&line[start_col..end_col]).max(1)
+            } else {
+                1
+            };
+            let (marker, marker_style) = if is_primary {
+                ('^', level_style)
+            } else {
+                ('-', gutter_style)
+            };
+            let _ = writeln!(
+                out,
+                "{} {}|{} {}{}{} {}{}",
+                blank_gutter,
+                gutter_style,
+                reset,
+                " ".repeat(padding),
+                marker_style,
+                marker.to_string().repeat(underline_width),
+                label.message,
+                reset
+            );
+        }
+
+        for help in &self.help {
+            let _ = writeln!(
+                out,
+                "{} {}={} {}help{}: {}",
+                blank_gutter,
+                gutter_style,
+                reset,
+                paint(BOLD),
+                reset,
+                help
+            );
+        }
+
+        out
+    }
+}
+
+impl From<&Loc<ParseError>> for Diagnostic {
+    fn from(error: &Loc<ParseError>) -> Self {
+        let message = error.inner().to_string();
+        let label = |message: String| Label {
+            span: error.range(),
+            message,
+        };
+
+        match error.inner() {
+            ParseError::UnmatchedRightBracket => Diagnostic::new(
+                Level::Error,
+                message,
+                label("unmatched bracket".to_string()),
+            )
+            .with_help("you can escape the bracket by prefixing it with `\\`"),
+            ParseError::EndOfFile { expected } => Diagnostic::new(
+                Level::Error,
+                message,
+                label(format!("expected {} here", expected)),
+            ),
+            ParseError::UnexpectedChar { expected, .. } => Diagnostic::new(
+                Level::Error,
+                message,
+                label(format!("expected {}", expected)),
+            ),
+            ParseError::UnclosedElement { opener, expected } => Diagnostic::new(
+                Level::Error,
+                message,
+                label(format!("expected {} here", expected)),
+            )
+            .with_label(opener.clone(), "element opened here"),
+            ParseError::UnclosedQuote { opener } => {
+                Diagnostic::new(Level::Error, message, label("expected \" here".to_string()))
+                    .with_label(opener.clone(), "quote opened here")
+            }
+        }
+    }
+}
+
+impl From<&Loc<ValidationError>> for Diagnostic {
+    fn from(error: &Loc<ValidationError>) -> Self {
+        let message = error.inner().to_string();
+        let label = |message: &str| Label {
+            span: error.range(),
+            message: message.to_string(),
+        };
+
+        match error.inner() {
+            ValidationError::UnknownElement {
+                suggestion,
+                fixable,
+                ..
+            } => {
+                let diagnostic = Diagnostic::new(Level::Error, message, label("unknown element"));
+                match suggestion {
+                    Some(suggestion) if *fixable => {
+                        diagnostic.with_fix(error.range(), suggestion.clone())
+                    }
+                    _ => diagnostic,
+                }
+            }
+            ValidationError::WrongKind { name, expected, .. } => {
+                let example = match expected {
+                    ElementKind::Inline => format!("[{} …]", name),
+                    ElementKind::Block => format!("{{{}| …}}", name),
+                };
+                Diagnostic::new(Level::Error, message, label("used with the wrong brackets"))
+                    .with_help(format!("write it as `{}`", example))
+            }
+            ValidationError::WrongArgCount { expected, .. } => Diagnostic::new(
+                Level::Error,
+                message,
+                label(&format!("expected {}", expected)),
+            ),
+            ValidationError::DisallowedChild { .. } => {
+                Diagnostic::new(Level::Error, message, label("not allowed here"))
+            }
+            ValidationError::MisplacedElement { .. } => {
+                Diagnostic::new(Level::Error, message, label("not allowed here"))
+            }
+        }
+    }
+}
+
+impl From<&Loc<ReferenceError>> for Diagnostic {
+    fn from(error: &Loc<ReferenceError>) -> Self {
+        let message = error.inner().to_string();
+        let label = |message: &str| Label {
+            span: error.range(),
+            message: message.to_string(),
+        };
+
+        match error.inner() {
+            ReferenceError::UndefinedLabel { .. } => {
+                Diagnostic::new(Level::Error, message, label("no element has this label"))
+                    .with_help("add `label=…` to the element to reference")
+            }
+            ReferenceError::DuplicateLabel { first, .. } => {
+                Diagnostic::new(Level::Error, message, label("defined again here"))
+                    .with_label(first.clone(), "first defined here")
+            }
+            ReferenceError::EmptyReference => {
+                Diagnostic::new(Level::Error, message, label("expected a label"))
+            }
+            ReferenceError::InvalidLabel { .. } => {
+                Diagnostic::new(Level::Error, message, label("invalid label")).with_help(format!(
+                    "labels can't contain any of `{}`",
+                    LABEL_SPECIAL_CHARS
+                ))
+            }
+        }
+    }
+}
+
+// The converter still produces usable markup, so its errors are warnings
+impl From<&Loc<MathError>> for Diagnostic {
+    fn from(error: &Loc<MathError>) -> Self {
+        let message = error.inner().to_string();
+        let label = |message: &str| Label {
+            span: error.range(),
+            message: message.to_string(),
+        };
+
+        match error.inner() {
+            MathError::UnsupportedCommand { .. } | MathError::UnsupportedEnvironment { .. } => {
+                Diagnostic::new(Level::Warning, message, label("rendered as an error"))
+                    .with_help("MathJax and KaTeX support more of TeX than the MathML converter")
+            }
+            MathError::MissingArgument { .. } => {
+                Diagnostic::new(Level::Warning, message, label("expected an argument here"))
+            }
+            MathError::UnmatchedRightBrace => {
+                Diagnostic::new(Level::Warning, message, label("unmatched brace"))
+            }
+            MathError::UnclosedGroup { opener } => {
+                Diagnostic::new(Level::Warning, message, label("expected } here"))
+                    .with_label(opener.clone(), "group opened here")
+            }
+            MathError::UnclosedEnvironment { opener, .. } => {
+                Diagnostic::new(Level::Warning, message, label("expected \\end here"))
+                    .with_label(opener.clone(), "environment opened here")
+            }
+            MathError::MismatchedEnvironment { expected, .. } => Diagnostic::new(
+                Level::Warning,
+                message,
+                label(&format!("expected \\end{{{}}}", expected)),
+            ),
+            MathError::Misplaced { .. } => {
+                Diagnostic::new(Level::Warning, message, label("outside of a matrix"))
+            }
+        }
+    }
+}
+
+/// Applies fixes to `source`. A fix overlapping one that comes before it is
+/// skipped, so the result is well defined for any set of fixes.
+pub fn apply_fixes(source: &str, fixes: &[Fix]) -> String {
+    let mut fixes: Vec<&Fix> = fixes.iter().collect();
+    fixes.sort_by_key(|fix| (fix.span.start, fix.span.end));
+
+    let mut fixed = String::with_capacity(source.len());
+    let mut idx = 0;
+    for fix in fixes {
+        if fix.span.start < idx {
+            continue;
+        }
+        fixed.push_str(&source[idx..fix.span.start]);
+        fixed.push_str(&fix.replacement);
+        idx = fix.span.end;
+    }
+    fixed.push_str(&source[idx..]);
+    fixed
+}
+
+fn expand_tabs(text: &str) -> String {
+    text.replace('\t', &" ".repeat(TAB_WIDTH))
+}
+
+// Columns taken up in a terminal, so that wide chars like CJK are
+// underlined correctly
+fn display_width(text: &str) -> usize {
+    text.chars()
+        .map(|c| match c {
+            '\t' => TAB_WIDTH,
+            _ => c.width().unwrap_or(0),
+        })
+        .sum()
+}
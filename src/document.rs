@@ -0,0 +1,172 @@
+use crate::parser::{unescape, Expr, Loc, ParseError, Parser, Span};
+use std::borrow::Cow;
+
+/// A parsed document borrowing its source. Nodes hand out names, arguments
+/// and text as slices of the source without copying them.
+///
+/// ```
+/// use cayatex::Parser;
+///
+/// let source = String::from("hello [link \"https://example.com\" | world]");
+/// let document = Parser::new(&source).parse().unwrap();
+/// let link = document.nodes().nth(1).unwrap();
+/// assert_eq!(link.name(), Some("link"));
+/// assert_eq!(link.args().next(), Some("https://example.com"));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Document<'src> {
+    source: &'src str,
+    exprs: Vec<Loc<Expr>>,
+}
+
+/// A parsed document that owns its source, for when the tree has to outlive
+/// the buffer it was parsed from.
+#[derive(Debug, Clone)]
+pub struct OwnedDocument {
+    source: String,
+    exprs: Vec<Loc<Expr>>,
+}
+
+/// A node of a `Document`. Copying it is cheap.
+#[derive(Debug, Clone, Copy)]
+pub struct Node<'a, 'src> {
+    source: &'src str,
+    expr: &'a Loc<Expr>,
+}
+
+impl<'src> Document<'src> {
+    pub fn new(source: &'src str, exprs: Vec<Loc<Expr>>) -> Self {
+        Document { source, exprs }
+    }
+
+    pub fn source(&self) -> &'src str {
+        self.source
+    }
+
+    pub fn exprs(&self) -> &[Loc<Expr>] {
+        &self.exprs
+    }
+
+    pub fn nodes<'a>(&'a self) -> impl Iterator<Item = Node<'a, 'src>> + 'a {
+        nodes(self.source, &self.exprs)
+    }
+
+    pub fn into_owned(self) -> OwnedDocument {
+        OwnedDocument {
+            source: self.source.to_string(),
+            exprs: self.exprs,
+        }
+    }
+}
+
+impl OwnedDocument {
+    pub fn parse(source: String) -> Result<Self, Loc<ParseError>> {
+        let exprs = Parser::new(&source).parse_document()?;
+        Ok(OwnedDocument { source, exprs })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    pub fn exprs(&self) -> &[Loc<Expr>] {
+        &self.exprs
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = Node<'_, '_>> {
+        nodes(&self.source, &self.exprs)
+    }
+}
+
+fn nodes<'a, 'src>(
+    source: &'src str,
+    exprs: &'a [Loc<Expr>],
+) -> impl Iterator<Item = Node<'a, 'src>> + 'a
+where
+    'src: 'a,
+{
+    exprs.iter().map(move |expr| Node { source, expr })
+}
+
+impl<'a, 'src: 'a> Node<'a, 'src> {
+    pub fn expr(&self) -> &'a Loc<Expr> {
+        self.expr
+    }
+
+    pub fn range(&self) -> Span {
+        self.expr.range()
+    }
+
+    /// The source of the whole node
+    pub fn as_str(&self) -> &'src str {
+        &self.source[self.expr.range()]
+    }
+
+    pub fn is_inline(&self) -> bool {
+        matches!(self.expr.inner(), Expr::Inline { .. })
+    }
+
+    pub fn is_block(&self) -> bool {
+        matches!(self.expr.inner(), Expr::Block { .. })
+    }
+
+    /// The name of an inline, block or math element
+    pub fn name(&self) -> Option<&'src str> {
+        match self.expr.inner() {
+            Expr::Inline { name, .. } | Expr::Block { name, .. } => {
+                Some(&self.source[name.clone()])
+            }
+            Expr::Math(math) => math.name.clone().map(|name| &self.source[name]),
+            Expr::Text(_) | Expr::Raw(_) | Expr::Error => None,
+        }
+    }
+
+    /// The arguments of an element as written, escapes included
+    pub fn args(&self) -> impl Iterator<Item = &'src str> + 'a {
+        let source = self.source;
+        let args: &'a [Span] = match self.expr.inner() {
+            Expr::Inline { args, .. } | Expr::Block { args, .. } => args,
+            Expr::Math(math) => &math.args,
+            Expr::Text(_) | Expr::Raw(_) | Expr::Error => &[],
+        };
+        args.iter().map(move |arg| &source[arg.clone()])
+    }
+
+    pub fn is_raw(&self) -> bool {
+        matches!(self.expr.inner(), Expr::Raw(_))
+    }
+
+    pub fn is_math(&self) -> bool {
+        matches!(self.expr.inner(), Expr::Math(_))
+    }
+
+    /// The text of a text or raw node as written, escapes included, or the
+    /// TeX of a math node
+    pub fn text(&self) -> Option<&'src str> {
+        match self.expr.inner() {
+            Expr::Text(span) | Expr::Raw(span) => Some(&self.source[span.clone()]),
+            Expr::Math(math) => Some(&self.source[math.tex.clone()]),
+            _ => None,
+        }
+    }
+
+    /// The text of a text node with escapes removed, or the text of a raw or
+    /// math node as is. Only allocates if the text contains escapes.
+    pub fn unescaped_text(&self) -> Option<Cow<'src, str>> {
+        match self.expr.inner() {
+            Expr::Text(span) => Some(unescape(&self.source[span.clone()])),
+            Expr::Raw(span) => Some(Cow::Borrowed(&self.source[span.clone()])),
+            Expr::Math(math) => Some(Cow::Borrowed(&self.source[math.tex.clone()])),
+            _ => None,
+        }
+    }
+
+    /// The body of an element
+    pub fn children(&self) -> impl Iterator<Item = Node<'a, 'src>> {
+        let body: &'a [Loc<Expr>] = match self.expr.inner() {
+            Expr::Inline { body, .. } | Expr::Block { body, .. } => body,
+            Expr::Text(_) | Expr::Raw(_) | Expr::Math(_) | Expr::Error => &[],
+        };
+        nodes(self.source, body)
+    }
+}
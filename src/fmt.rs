@@ -0,0 +1,311 @@
+use crate::parser::{Expr, Loc, Span};
+use unicode_width::UnicodeWidthStr;
+
+pub struct FormatOptions {
+    /// Paragraphs are wrapped to fit in this many columns where possible
+    pub width: usize,
+    /// Spaces per level of block nesting
+    pub indent: usize,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        FormatOptions {
+            width: 80,
+            indent: 2,
+        }
+    }
+}
+
+/// Formats a document into its canonical form. Runs of whitespace are
+/// treated as a single space, except for blank lines which separate
+/// paragraphs. Paragraphs are re-wrapped, block bodies go on their own
+/// indented lines and the spacing inside element headers is normalised to
+/// `[name args | body]` and `{name args|`, with whitespace at the end of an
+/// inline body moved after the `]`. The bodies of raw and math elements are
+/// kept exactly as written, except that the closing bracket of a raw block
+/// on its own line is re-indented along with its header.
+///
+/// Formatting is idempotent. The document should parse without errors.
+pub fn format(source: &str, exprs: &[Loc<Expr>], options: &FormatOptions) -> String {
+    let mut formatter = Formatter {
+        source,
+        options,
+        out: String::new(),
+        depth: 0,
+        words: Vec::new(),
+        word: String::new(),
+        space: false,
+        blank_line: false,
+        flow_empty: true,
+    };
+    formatter.format_exprs(exprs);
+    formatter.flush_paragraph();
+    formatter.out
+}
+
+struct Formatter<'a> {
+    source: &'a str,
+    options: &'a FormatOptions,
+    out: String,
+    depth: usize,
+    // Words of the paragraph being built, along with the word being built.
+    // Pieces of source that aren't separated by whitespace are glued into
+    // one word so that we never insert whitespace that wasn't there.
+    words: Vec<String>,
+    word: String,
+    // Whether there is whitespace before the next piece
+    space: bool,
+    // Whether a blank line separates the next line from the previous one
+    blank_line: bool,
+    // Whether nothing has been written at the current depth yet
+    flow_empty: bool,
+}
+
+impl<'a> Formatter<'a> {
+    fn format_exprs(&mut self, exprs: &[Loc<Expr>]) {
+        for expr in exprs {
+            match expr.inner() {
+                Expr::Inline { body, .. } if is_raw(body) => self.push(&self.source[expr.range()]),
+                Expr::Block { name, args, body } if is_raw(body) => {
+                    let raw = body[0].range();
+                    // The body is only the closing bracket's indentation
+                    if self.source[raw.clone()].trim().is_empty()
+                        && !self.source[raw.clone()].contains('\n')
+                    {
+                        self.format_block(name, args, &[]);
+                    } else {
+                        self.push_raw_block(expr.range(), raw);
+                    }
+                }
+                Expr::Math(math) if math.display && math.name.is_some() => {
+                    self.push_raw_block(expr.range(), math.tex.clone())
+                }
+                Expr::Math(_) => self.push(&self.source[expr.range()]),
+                Expr::Inline { name, args, body } => {
+                    self.format_inline(expr.range(), name, args, body)
+                }
+                Expr::Block { name, args, body } => self.format_block(name, args, body),
+                Expr::Text(span) => self.format_text(span),
+                // Only found in the body of a raw element, which is handled
+                // above
+                Expr::Raw(span) => self.push(&self.source[span.clone()]),
+                Expr::Error => self.push(&self.source[expr.range()]),
+            }
+        }
+    }
+
+    fn format_text(&mut self, span: &Span) {
+        let text = &self.source[span.clone()];
+        let mut newlines = 0;
+        let mut word_start = None;
+        for (idx, c) in text.char_indices() {
+            if c.is_whitespace() {
+                if let Some(start) = word_start.take() {
+                    self.push(&text[start..idx]);
+                }
+                self.space = true;
+                if c == '\n' {
+                    newlines += 1;
+                    if newlines == 2 {
+                        self.paragraph_break();
+                    }
+                }
+            } else {
+                newlines = 0;
+                word_start.get_or_insert(idx);
+            }
+        }
+        if let Some(start) = word_start {
+            self.push(&text[start..]);
+        }
+    }
+
+    fn format_inline(&mut self, range: Span, name: &Span, args: &[Span], body: &[Loc<Expr>]) {
+        self.push("[");
+        self.push(&self.source[name.clone()]);
+        // A `|` is allowed without any arguments, and is then still needed to
+        // keep a body starting with `|` from being read as the separator
+        let header_end = args.last().map_or(name.end, |arg| arg.end);
+        let body_start = body.first().map_or(range.end, |expr| expr.range().start);
+        if self.source[header_end..body_start].contains('|') {
+            for arg in args {
+                self.space = true;
+                self.push(&self.format_arg(arg));
+            }
+            self.space = true;
+            self.push("|");
+            self.space = !body.is_empty();
+        } else {
+            // Whitespace between the name and the body is significant since
+            // it's what ends the name
+            self.space = !body.is_empty() && body_start > name.end;
+        }
+        self.format_exprs(body);
+        // Whitespace before the `]` moves after it, unless it keeps a
+        // backslash from escaping the bracket
+        let space = self.space;
+        let space_inside = space && ends_with_backslash(&self.word);
+        self.space = space_inside;
+        self.push("]");
+        self.space = space && !space_inside;
+    }
+
+    fn format_block(&mut self, name: &Span, args: &[Span], body: &[Loc<Expr>]) {
+        self.flush_paragraph();
+        let mut header = format!("{{{}", &self.source[name.clone()]);
+        for arg in args {
+            header.push(' ');
+            header.push_str(&self.format_arg(arg));
+        }
+        if ends_with_backslash(&header) {
+            header.push(' ');
+        }
+        header.push('|');
+
+        if body.is_empty() {
+            header.push('}');
+            self.push_line(&header);
+        } else {
+            self.push_line(&header);
+            self.depth += 1;
+            self.flow_empty = true;
+            self.blank_line = false;
+            self.format_exprs(body);
+            self.flush_paragraph();
+            self.depth -= 1;
+            self.blank_line = false;
+            self.push_line("}");
+        }
+        self.space = false;
+    }
+
+    // The header and closing bracket are indented like any other block while
+    // the body is kept verbatim. A body ending in a line of whitespace is
+    // taken to be indenting the closing bracket, which gets the indentation
+    // of the header instead.
+    fn push_raw_block(&mut self, range: Span, body: Span) {
+        self.flush_paragraph();
+        let header = &self.source[range.start..body.start];
+        let mut text = if header.contains('\n') {
+            format!("{}\n", header.trim_end())
+        } else {
+            header.to_string()
+        };
+        let header_len = text.len();
+        text.push_str(&self.source[body.clone()]);
+
+        let closer = &self.source[body.end..range.end];
+        let closer_line = match text.rfind('\n') {
+            // Nothing but indentation is left of the body, so it's written
+            // like an empty block
+            Some(idx) if idx < header_len && text[(idx + 1)..].trim().is_empty() => {
+                text.truncate(idx);
+                text.push_str(closer);
+                false
+            }
+            Some(idx) if text[(idx + 1)..].trim().is_empty() => {
+                text.truncate(idx);
+                true
+            }
+            _ => {
+                text.push_str(closer);
+                false
+            }
+        };
+        let mut lines = text.split('\n');
+        if let Some(first) = lines.next() {
+            self.push_line(first);
+        }
+        for line in lines {
+            self.out.push_str(line);
+            self.out.push('\n');
+        }
+        if closer_line {
+            self.push_line(closer);
+        }
+        self.space = false;
+    }
+
+    // Arguments are written bare unless they need quotes
+    fn format_arg(&self, arg: &Span) -> String {
+        let text = &self.source[arg.clone()];
+        let needs_quotes = text.is_empty()
+            || text
+                .chars()
+                .any(|c| c.is_whitespace() || "[]{}|\"".contains(c));
+        if needs_quotes {
+            // A trailing backslash would escape the closing quote
+            let backslash = if ends_with_backslash(text) { "\\" } else { "" };
+            format!("\"{}{}\"", text, backslash)
+        } else {
+            text.to_string()
+        }
+    }
+
+    fn push(&mut self, piece: &str) {
+        if self.space && !self.word.is_empty() {
+            self.words.push(std::mem::take(&mut self.word));
+        }
+        self.space = false;
+        self.word.push_str(piece);
+    }
+
+    fn paragraph_break(&mut self) {
+        self.flush_paragraph();
+        if !self.flow_empty {
+            self.blank_line = true;
+        }
+    }
+
+    fn flush_paragraph(&mut self) {
+        if !self.word.is_empty() {
+            self.words.push(std::mem::take(&mut self.word));
+        }
+
+        let indent_width = self.depth * self.options.indent;
+        let mut line = String::new();
+        let mut line_width = 0;
+        for word in std::mem::take(&mut self.words) {
+            // Columns in a terminal, so wide CJK chars count twice
+            let word_width = word.width();
+            if !line.is_empty() && indent_width + line_width + 1 + word_width > self.options.width {
+                self.push_line(&line);
+                line.clear();
+                line_width = 0;
+            }
+            if !line.is_empty() {
+                line.push(' ');
+                line_width += 1;
+            }
+            line.push_str(&word);
+            line_width += word_width;
+        }
+        if !line.is_empty() {
+            self.push_line(&line);
+        }
+    }
+
+    fn push_line(&mut self, line: &str) {
+        if self.blank_line {
+            self.out.push('\n');
+            self.blank_line = false;
+        }
+        for _ in 0..self.depth * self.options.indent {
+            self.out.push(' ');
+        }
+        self.out.push_str(line);
+        self.out.push('\n');
+        self.flow_empty = false;
+    }
+}
+
+fn is_raw(body: &[Loc<Expr>]) -> bool {
+    matches!(body, [expr] if matches!(expr.inner(), Expr::Raw(_)))
+}
+
+// Whether `text` ends with a backslash that isn't part of an escape, in which
+// case gluing a bracket onto it would escape the bracket
+fn ends_with_backslash(text: &str) -> bool {
+    text.chars().rev().take_while(|&c| c == '\\').count() % 2 == 1
+}
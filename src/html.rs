@@ -0,0 +1,337 @@
+use crate::mathml;
+use crate::parser::{args_text, label, positional_args, unescape, Expr, Loc, Math, Span};
+use crate::refs::{self, References};
+
+/// Renders a document to an HTML fragment.
+///
+/// Known elements map onto the matching HTML tags. Unknown inline elements
+/// become `<span class="cayatex-NAME">` and unknown blocks
+/// `<div class="cayatex-NAME">` so that no content is dropped, and the
+/// arguments of inline elements that don't take any are kept as text. Math
+/// goes
+/// inside an element with the `math` class, see `MathOutput`.
+///
+/// Labelled elements get their label as `id` and numbered elements show
+/// their number. References link to them with text such as `Theorem 3`.
+/// Undefined references render as `??`.
+pub fn render(source: &str, exprs: &[Loc<Expr>]) -> String {
+    render_with_options(source, exprs, &HtmlOptions::default())
+}
+
+pub fn render_with_options(source: &str, exprs: &[Loc<Expr>], options: &HtmlOptions) -> String {
+    let (references, _) = refs::resolve(source, exprs);
+    let mut renderer = HtmlRenderer {
+        source,
+        options,
+        references,
+        out: String::new(),
+    };
+    renderer.render_exprs(exprs);
+    renderer.out
+}
+
+pub struct HtmlOptions {
+    pub math: MathOutput,
+}
+
+impl Default for HtmlOptions {
+    fn default() -> Self {
+        HtmlOptions {
+            math: MathOutput::Delimiters,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MathOutput {
+    /// The TeX between `\(…\)` or `\[…\]` delimiters, for MathJax or KaTeX
+    /// to typeset
+    Delimiters,
+    /// MathML converted by `mathml::convert`, which needs no JavaScript.
+    /// Conversion errors are rendered as `<merror>`; use `mathml::check` to
+    /// report them.
+    MathMl,
+}
+
+struct HtmlRenderer<'a> {
+    source: &'a str,
+    options: &'a HtmlOptions,
+    references: References,
+    out: String,
+}
+
+impl<'a> HtmlRenderer<'a> {
+    fn render_exprs(&mut self, exprs: &[Loc<Expr>]) {
+        for expr in exprs {
+            self.render_expr(expr);
+        }
+    }
+
+    fn render_expr(&mut self, expr: &Loc<Expr>) {
+        match expr.inner() {
+            Expr::Inline { name, args, body } => self.render_inline(name, args, body),
+            Expr::Block { name, args, body } => self.render_block(&expr.range(), name, args, body),
+            Expr::Text(span) => self.push_text(&unescape(&self.source[span.clone()])),
+            Expr::Raw(span) => self.push_text(&self.source[span.clone()]),
+            Expr::Math(math) => self.render_math(&expr.range(), math),
+            Expr::Error => {
+                self.out.push_str("<span class=\"cayatex-error\">");
+                self.push_text(&self.source[expr.range()]);
+                self.out.push_str("</span>");
+            }
+        }
+    }
+
+    fn render_inline(&mut self, name: &Span, args: &[Span], body: &[Loc<Expr>]) {
+        if let Some(label) = refs::reference_label(self.source, name, body) {
+            self.render_reference(&label);
+            return;
+        }
+        let name_span = name;
+        let name = &self.source[name.clone()];
+        let tag = match name {
+            "bold" | "strong" => "strong",
+            "italic" | "emph" => "em",
+            "code" | "verbatim" => "code",
+            "underline" => "u",
+            "strike" => "s",
+            "sub" => "sub",
+            "sup" => "sup",
+            "link" => {
+                self.out.push_str("<a");
+                if let Some(href) = args.first() {
+                    self.push_attribute("href", href);
+                }
+                self.out.push('>');
+                if body.is_empty() {
+                    if let Some(href) = args.first() {
+                        self.push_text(&unescape(&self.source[href.clone()]));
+                    }
+                } else {
+                    self.render_exprs(body);
+                }
+                self.out.push_str("</a>");
+                return;
+            }
+            "image" => {
+                self.out.push_str("<img");
+                if let Some(src) = args.first() {
+                    self.push_attribute("src", src);
+                }
+                self.out.push_str(" alt=\"");
+                for expr in body {
+                    if let Expr::Text(span) = expr.inner() {
+                        push_escaped(&mut self.out, &unescape(&self.source[span.clone()]));
+                    }
+                }
+                self.out.push_str("\">");
+                return;
+            }
+            _ => {
+                let args = args_text(self.source, name_span, args);
+                self.render_unknown("span", name, None, None, args, body);
+                return;
+            }
+        };
+
+        self.out.push('<');
+        self.out.push_str(tag);
+        self.out.push('>');
+        if tag != "code" {
+            if let Some(args) = args_text(self.source, name_span, args) {
+                self.push_text(&unescape(args));
+            }
+        }
+        self.render_exprs(body);
+        self.out.push_str("</");
+        self.out.push_str(tag);
+        self.out.push('>');
+    }
+
+    fn render_block(&mut self, range: &Span, name: &Span, args: &[Span], body: &[Loc<Expr>]) {
+        let name = &self.source[name.clone()];
+        let label = label(self.source, args);
+        let args = &positional_args(self.source, args);
+        let number = self.references.number(range);
+        let (tag, class) = match name {
+            "paragraph" => ("p", None),
+            "section" => ("section", None),
+            "quote" => ("blockquote", None),
+            "code" | "verbatim" => ("pre", None),
+            "list" => ("ul", None),
+            "enumerate" => ("ol", None),
+            "item" => ("li", None),
+            "title" => ("h1", None),
+            "heading" => ("h2", None),
+            "theorem" | "lemma" | "corollary" | "definition" | "proof" | "example" => {
+                ("div", Some(name))
+            }
+            _ => {
+                self.render_unknown("div", name, label.as_ref(), number, None, body);
+                return;
+            }
+        };
+
+        // Lists and preformatted text can't hold the number themselves
+        let number_inside = !matches!(tag, "ul" | "ol" | "pre");
+        if let (Some(number), false) = (number, number_inside) {
+            self.push_number(&format!("{} {}.", refs::display_name(name), number));
+        }
+        self.out.push('<');
+        self.out.push_str(tag);
+        if let Some(class) = class {
+            self.out.push_str(" class=\"");
+            push_escaped(&mut self.out, class);
+            self.out.push('"');
+        }
+        if let Some(label) = &label {
+            self.push_attribute("id", label);
+        }
+        self.out.push('>');
+        // Sections take their heading as an argument
+        if name == "section" {
+            let title = args.first();
+            if title.is_some() || number.is_some() {
+                self.out.push_str("<h2>");
+                if let Some(number) = number {
+                    self.push_number(&number.to_string());
+                }
+                if let Some(title) = title {
+                    if number.is_some() {
+                        self.out.push(' ');
+                    }
+                    self.push_text(&unescape(&self.source[title.clone()]));
+                }
+                self.out.push_str("</h2>");
+            }
+        } else if let (Some(number), true) = (number, number_inside) {
+            self.push_number(&format!("{} {}.", refs::display_name(name), number));
+            self.out.push(' ');
+        }
+        // Code blocks take their language as an argument
+        if name == "code" {
+            if let Some(language) = args.first() {
+                self.out.push_str("<code class=\"language-");
+                push_escaped(&mut self.out, &unescape(&self.source[language.clone()]));
+                self.out.push_str("\">");
+                self.render_exprs(body);
+                self.out.push_str("</code></pre>\n");
+                return;
+            }
+        }
+        self.render_exprs(body);
+        self.out.push_str("</");
+        self.out.push_str(tag);
+        self.out.push_str(">\n");
+    }
+
+    fn render_math(&mut self, range: &Span, math: &Math) {
+        let (tag, open, close) = if math.display {
+            ("div", "\\[", "\\]")
+        } else {
+            ("span", "\\(", "\\)")
+        };
+        self.out.push('<');
+        self.out.push_str(tag);
+        self.out.push_str(" class=\"math\"");
+        if let Some(label) = &math.label {
+            self.push_attribute("id", label);
+        }
+        self.out.push('>');
+        match self.options.math {
+            MathOutput::Delimiters => {
+                self.out.push_str(open);
+                self.push_text(&self.source[math.tex.clone()]);
+                self.out.push_str(close);
+            }
+            MathOutput::MathMl => self.out.push_str(&mathml::convert(self.source, math).0),
+        }
+        if let Some(number) = self.references.number(range) {
+            self.push_number(&format!("({})", number));
+        }
+        self.out.push_str("</");
+        self.out.push_str(tag);
+        self.out.push('>');
+        if math.display {
+            self.out.push('\n');
+        }
+    }
+
+    fn render_reference(&mut self, label: &str) {
+        match self.references.get(label) {
+            Some(target) => {
+                self.out.push_str("<a class=\"cayatex-ref\" href=\"#");
+                push_escaped(&mut self.out, label);
+                self.out.push_str("\">");
+                push_escaped(&mut self.out, &target.text());
+                self.out.push_str("</a>");
+            }
+            None => self.out.push_str("<span class=\"cayatex-error\">??</span>"),
+        }
+    }
+
+    fn render_unknown(
+        &mut self,
+        tag: &str,
+        name: &str,
+        label: Option<&Span>,
+        number: Option<usize>,
+        args: Option<&str>,
+        body: &[Loc<Expr>],
+    ) {
+        self.out.push('<');
+        self.out.push_str(tag);
+        self.out.push_str(" class=\"cayatex-");
+        push_escaped(&mut self.out, name);
+        self.out.push('"');
+        if let Some(label) = label {
+            self.push_attribute("id", label);
+        }
+        self.out.push('>');
+        if let Some(number) = number {
+            self.push_number(&format!("{} {}.", refs::display_name(name), number));
+            self.out.push(' ');
+        }
+        if let Some(args) = args {
+            self.push_text(&unescape(args));
+        }
+        self.render_exprs(body);
+        self.out.push_str("</");
+        self.out.push_str(tag);
+        self.out.push('>');
+        if tag == "div" {
+            self.out.push('\n');
+        }
+    }
+
+    fn push_attribute(&mut self, name: &str, value: &Span) {
+        self.out.push(' ');
+        self.out.push_str(name);
+        self.out.push_str("=\"");
+        push_escaped(&mut self.out, &unescape(&self.source[value.clone()]));
+        self.out.push('"');
+    }
+
+    fn push_number(&mut self, number: &str) {
+        self.out.push_str("<span class=\"cayatex-number\">");
+        push_escaped(&mut self.out, number);
+        self.out.push_str("</span>");
+    }
+
+    fn push_text(&mut self, text: &str) {
+        push_escaped(&mut self.out, text);
+    }
+}
+
+fn push_escaped(out: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+}
@@ -0,0 +1,176 @@
+//! The JSON representation of a parsed document, for tools written in other
+//! languages. Requires the `serde` feature.
+//!
+//! The schema is versioned by the top level `version` field, which changes
+//! whenever a field is removed or its meaning changes. Adding fields or node
+//! types doesn't change the version, so consumers should skip nodes with a
+//! `type` they don't know. This is version 1:
+//!
+//! ```text
+//! Document = { "version": 1, "nodes": [Node], "errors": [Error] }
+//!
+//! Node = { "type": "inline" | "block", "range": Range, "name": Text,
+//!          "args": [Text], "body": [Node] }
+//!      | { "type": "text", "range": Range, "text": string }
+//!      | { "type": "raw", "range": Range, "text": string }
+//!      | { "type": "math", "range": Range, "display": bool,
+//!          "name": Text | null, "args": [Text], "label": Text | null,
+//!          "tex": string }
+//!      | { "type": "error", "range": Range, "text": string }
+//!
+//! Text  = { "range": Range, "text": string }
+//! Error = { "range": Range, "line": number, "column": number,
+//!           "message": string, "opener": Range | null }
+//! Range = [start, end]
+//! ```
+//!
+//! Ranges are byte offsets into the UTF-8 source, with `end` exclusive.
+//! `text` is the resolved text with escape sequences removed, except for
+//! `raw` nodes, the bodies of raw elements, and `error` nodes, the skipped
+//! source, where it's the source as written. `tex` is always as written, and
+//! `name` is null for math written with `$` delimiters. Lines and columns
+//! are 1-based, with columns counted in UTF-8 bytes. `opener` is set for
+//! unclosed elements and points at the opening bracket.
+
+use crate::line_index::LineIndex;
+use crate::parser::{unescape, Expr, Loc, ParseError, Span};
+use serde::Serialize;
+use std::borrow::Cow;
+
+pub const VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct Document<'a> {
+    version: u32,
+    nodes: Vec<Node<'a>>,
+    errors: Vec<Error>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Node<'a> {
+    Inline {
+        range: [usize; 2],
+        name: Text<'a>,
+        args: Vec<Text<'a>>,
+        body: Vec<Node<'a>>,
+    },
+    Block {
+        range: [usize; 2],
+        name: Text<'a>,
+        args: Vec<Text<'a>>,
+        body: Vec<Node<'a>>,
+    },
+    Text {
+        range: [usize; 2],
+        text: Cow<'a, str>,
+    },
+    Raw {
+        range: [usize; 2],
+        text: &'a str,
+    },
+    Math {
+        range: [usize; 2],
+        display: bool,
+        name: Option<Text<'a>>,
+        args: Vec<Text<'a>>,
+        label: Option<Text<'a>>,
+        tex: &'a str,
+    },
+    Error {
+        range: [usize; 2],
+        text: &'a str,
+    },
+}
+
+#[derive(Serialize)]
+struct Text<'a> {
+    range: [usize; 2],
+    text: Cow<'a, str>,
+}
+
+#[derive(Serialize)]
+struct Error {
+    range: [usize; 2],
+    line: usize,
+    column: usize,
+    message: String,
+    opener: Option<[usize; 2]>,
+}
+
+/// Serializes a document and its errors as described in the module docs.
+pub fn to_json(source: &str, exprs: &[Loc<Expr>], errors: &[Loc<ParseError>]) -> String {
+    let index = LineIndex::new(source);
+    let document = Document {
+        version: VERSION,
+        nodes: exprs.iter().map(|expr| node(source, expr)).collect(),
+        errors: errors.iter().map(|error| to_error(&index, error)).collect(),
+    };
+
+    serde_json::to_string(&document).expect("documents always serialize")
+}
+
+fn node<'a>(source: &'a str, expr: &Loc<Expr>) -> Node<'a> {
+    let range = to_range(&expr.range());
+    let text = |span: &Span| Text {
+        range: to_range(span),
+        text: unescape(&source[span.clone()]),
+    };
+
+    match expr.inner() {
+        Expr::Inline { name, args, body } => Node::Inline {
+            range,
+            name: text(name),
+            args: args.iter().map(text).collect(),
+            body: body.iter().map(|expr| node(source, expr)).collect(),
+        },
+        Expr::Block { name, args, body } => Node::Block {
+            range,
+            name: text(name),
+            args: args.iter().map(text).collect(),
+            body: body.iter().map(|expr| node(source, expr)).collect(),
+        },
+        Expr::Text(span) => Node::Text {
+            range,
+            text: unescape(&source[span.clone()]),
+        },
+        Expr::Raw(span) => Node::Raw {
+            range,
+            text: &source[span.clone()],
+        },
+        Expr::Math(math) => Node::Math {
+            range,
+            display: math.display,
+            name: math.name.as_ref().map(text),
+            args: math.args.iter().map(text).collect(),
+            label: math.label.as_ref().map(text),
+            tex: &source[math.tex.clone()],
+        },
+        Expr::Error => Node::Error {
+            range,
+            text: &source[expr.range()],
+        },
+    }
+}
+
+fn to_error(index: &LineIndex, error: &Loc<ParseError>) -> Error {
+    let position = index.line_col(error.range().start);
+    let opener = match error.inner() {
+        ParseError::UnclosedElement { opener, .. } | ParseError::UnclosedQuote { opener } => {
+            Some(to_range(opener))
+        }
+        _ => None,
+    };
+
+    Error {
+        range: to_range(&error.range()),
+        line: position.line,
+        column: position.col,
+        message: error.inner().to_string(),
+        opener,
+    }
+}
+
+fn to_range(span: &Span) -> [usize; 2] {
+    [span.start, span.end]
+}
@@ -0,0 +1,368 @@
+use crate::parser::{args_text, label, positional_args, unescape, Expr, Loc, Math, Span};
+use crate::refs::{self, References, NUMBERED};
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+
+// Environments from amsthm, along with the heading they're printed with
+const THEOREMS: &[(&str, &str)] = &[
+    ("theorem", "Theorem"),
+    ("lemma", "Lemma"),
+    ("corollary", "Corollary"),
+    ("definition", "Definition"),
+    ("example", "Example"),
+];
+
+/// Renders a document to a standalone LaTeX file. The preamble only loads
+/// the packages needed by the elements the document uses.
+///
+/// Elements without a LaTeX equivalent keep their body so that no content is
+/// dropped, and blocks are marked with a comment naming the element.
+///
+/// Labels become `\label`s, and references to elements LaTeX numbers
+/// become `\ref`s. References to other elements are written out with the
+/// number from `refs::resolve`.
+pub fn render(source: &str, exprs: &[Loc<Expr>]) -> String {
+    let (references, _) = refs::resolve(source, exprs);
+    let mut renderer = LatexRenderer {
+        source,
+        references,
+        out: String::new(),
+        used: BTreeSet::new(),
+    };
+    renderer.render_exprs(exprs);
+
+    let mut document = String::from(
+        "\\documentclass{article}\n\\usepackage[utf8]{inputenc}\n\\usepackage[T1]{fontenc}\n",
+    );
+    if renderer.used.contains("math") {
+        document.push_str("\\usepackage{amsmath}\n");
+    }
+    if renderer.used.contains("link") {
+        document.push_str("\\usepackage{hyperref}\n");
+    }
+    if renderer.used.contains("image") {
+        document.push_str("\\usepackage{graphicx}\n");
+    }
+    if renderer.used.contains("strike") {
+        document.push_str("\\usepackage[normalem]{ulem}\n");
+    }
+    let theorems: Vec<_> = THEOREMS
+        .iter()
+        .filter(|(name, _)| renderer.used.contains(*name))
+        .collect();
+    if !theorems.is_empty() || renderer.used.contains("proof") {
+        document.push_str("\\usepackage{amsthm}\n");
+    }
+    for (name, heading) in theorems {
+        document.push_str(&format!("\\newtheorem{{{}}}{{{}}}\n", name, heading));
+    }
+
+    document.push_str("\n\\begin{document}\n");
+    document.push_str(&renderer.out);
+    if !renderer.out.ends_with('\n') {
+        document.push('\n');
+    }
+    document.push_str("\\end{document}\n");
+    document
+}
+
+struct LatexRenderer<'a> {
+    source: &'a str,
+    references: References,
+    out: String,
+    // Names of the known elements in the document, used to build the preamble
+    used: BTreeSet<&'a str>,
+}
+
+impl<'a> LatexRenderer<'a> {
+    fn render_exprs(&mut self, exprs: &[Loc<Expr>]) {
+        for expr in exprs {
+            self.render_expr(expr);
+        }
+    }
+
+    fn render_expr(&mut self, expr: &Loc<Expr>) {
+        match expr.inner() {
+            Expr::Inline { name, args, body } => self.render_inline(name, args, body),
+            Expr::Block { name, args, body } => self.render_block(&expr.range(), name, args, body),
+            Expr::Text(span) => self.push_text(span),
+            Expr::Raw(span) => push_escaped(&mut self.out, &self.source[span.clone()]),
+            Expr::Math(math) => self.render_math(math),
+            Expr::Error => push_escaped(&mut self.out, &self.source[expr.range()]),
+        }
+    }
+
+    fn render_inline(&mut self, name: &Span, args: &[Span], body: &[Loc<Expr>]) {
+        if let Some(label) = refs::reference_label(self.source, name, body) {
+            self.render_reference(&label);
+            return;
+        }
+        let name_span = name;
+        let name = &self.source[name.clone()];
+        // Elements that don't take arguments keep them as text
+        let args_text = match name {
+            "link" | "image" | "code" | "verbatim" => None,
+            _ => args_text(self.source, name_span, args),
+        };
+        let command = match name {
+            "bold" | "strong" => "textbf",
+            "italic" | "emph" => "emph",
+            "code" | "verbatim" => "texttt",
+            "underline" => "underline",
+            "strike" => "sout",
+            "sub" => "textsubscript",
+            "sup" => "textsuperscript",
+            "link" => {
+                self.used.insert(name);
+                let url = args.first().map(|url| self.text(url)).unwrap_or_default();
+                if body.is_empty() {
+                    self.out.push_str("\\url{");
+                    push_escaped_url(&mut self.out, &url);
+                    self.out.push('}');
+                } else {
+                    self.out.push_str("\\href{");
+                    push_escaped_url(&mut self.out, &url);
+                    self.out.push_str("}{");
+                    self.render_exprs(body);
+                    self.out.push('}');
+                }
+                return;
+            }
+            "image" => {
+                self.used.insert(name);
+                if let Some(path) = args.first() {
+                    let path = self.text(path);
+                    self.out.push_str("\\includegraphics{");
+                    self.out.push_str(&path);
+                    self.out.push('}');
+                }
+                return;
+            }
+            _ => {
+                self.out.push('{');
+                if let Some(args) = args_text {
+                    push_escaped(&mut self.out, &unescape(args));
+                }
+                self.render_exprs(body);
+                self.out.push('}');
+                return;
+            }
+        };
+
+        self.used.insert(name);
+        self.out.push('\\');
+        self.out.push_str(command);
+        self.out.push('{');
+        if let Some(args) = args_text {
+            push_escaped(&mut self.out, &unescape(args));
+        }
+        self.render_exprs(body);
+        self.out.push('}');
+    }
+
+    fn render_block(&mut self, range: &Span, name: &Span, args: &[Span], body: &[Loc<Expr>]) {
+        let name = &self.source[name.clone()];
+        let label = label(self.source, args);
+        let args = &positional_args(self.source, args);
+        // LaTeX only numbers its own environments, so other blocks that
+        // references can point to get their number written out
+        if let (Some(number), false) = (self.references.number(range), NUMBERED.contains(&name)) {
+            self.start_line();
+            self.out.push_str("\\textbf{");
+            push_escaped(
+                &mut self.out,
+                &format!("{} {}.", refs::display_name(name), number),
+            );
+            self.out.push_str("}\n");
+        }
+        let environment = match name {
+            "paragraph" => {
+                self.start_line();
+                self.render_exprs(body);
+                self.out.push_str("\n\n");
+                return;
+            }
+            "section" | "heading" => {
+                let command = if name == "section" {
+                    "section"
+                } else {
+                    "subsection*"
+                };
+                self.start_line();
+                self.out.push('\\');
+                self.out.push_str(command);
+                self.out.push('{');
+                if let Some(title) = args.first() {
+                    self.push_text(title);
+                } else if name == "heading" {
+                    self.render_exprs(body);
+                }
+                self.out.push('}');
+                self.push_label(label.as_ref());
+                self.out.push('\n');
+                if name == "section" {
+                    self.render_exprs(body);
+                }
+                return;
+            }
+            "title" => {
+                self.start_line();
+                self.out.push_str("\\title{");
+                self.render_exprs(body);
+                self.out.push_str("}\n\\maketitle\n");
+                return;
+            }
+            "item" => {
+                self.start_line();
+                self.out.push_str("\\item ");
+                self.render_exprs(body);
+                self.out.push('\n');
+                return;
+            }
+            "code" | "verbatim" => {
+                self.start_line();
+                self.out.push_str("\\begin{verbatim}\n");
+                self.push_plain_text(body);
+                self.start_line();
+                self.out.push_str("\\end{verbatim}\n");
+                return;
+            }
+            "quote" => "quote",
+            "list" => "itemize",
+            "enumerate" => "enumerate",
+            "proof" => "proof",
+            _ if THEOREMS.iter().any(|(theorem, _)| *theorem == name) => name,
+            _ => {
+                self.start_line();
+                self.out.push_str("% ");
+                self.out.push_str(name);
+                self.out.push('\n');
+                self.render_exprs(body);
+                self.out.push_str("\n\n");
+                return;
+            }
+        };
+
+        self.used.insert(name);
+        self.start_line();
+        self.out.push_str("\\begin{");
+        self.out.push_str(environment);
+        self.out.push('}');
+        self.push_label(label.as_ref());
+        self.out.push('\n');
+        self.render_exprs(body);
+        self.start_line();
+        self.out.push_str("\\end{");
+        self.out.push_str(environment);
+        self.out.push_str("}\n");
+    }
+
+    // The TeX is passed through as is. `{equation|}` is numbered while
+    // `$$…$$` isn't.
+    fn render_math(&mut self, math: &Math) {
+        self.used.insert("math");
+        let tex = &self.source[math.tex.clone()];
+        if !math.display {
+            self.out.push_str("\\(");
+            self.out.push_str(tex);
+            self.out.push_str("\\)");
+            return;
+        }
+
+        self.start_line();
+        if math.name.is_none() {
+            self.out.push_str("\\[");
+            self.out.push_str(tex);
+            self.out.push_str("\\]\n");
+            return;
+        }
+        self.out.push_str("\\begin{equation}");
+        self.push_label(math.label.as_ref());
+        self.out.push('\n');
+        self.out.push_str(tex.trim_end());
+        self.out.push_str("\n\\end{equation}\n");
+    }
+
+    fn render_reference(&mut self, label: &str) {
+        match self.references.get(label) {
+            Some(target) if NUMBERED.contains(&target.name.as_str()) => {
+                push_escaped(&mut self.out, &refs::display_name(&target.name));
+                self.out.push_str("~\\ref{");
+                self.out.push_str(label);
+                self.out.push('}');
+            }
+            Some(target) => push_escaped(&mut self.out, &target.text()),
+            None => self.out.push_str("??"),
+        }
+    }
+
+    // Invalid labels are reported by `refs::resolve` and left out here, as
+    // they would break the LaTeX
+    fn push_label(&mut self, label: Option<&Span>) {
+        if let Some(label) = label {
+            let label = self.text(label);
+            if !refs::is_valid_label(&label) {
+                return;
+            }
+            self.out.push_str("\\label{");
+            self.out.push_str(&label);
+            self.out.push('}');
+        }
+    }
+
+    // Text of the body without any markup, for verbatim environments
+    fn push_plain_text(&mut self, body: &[Loc<Expr>]) {
+        for expr in body {
+            match expr.inner() {
+                Expr::Inline { body, .. } | Expr::Block { body, .. } => self.push_plain_text(body),
+                Expr::Text(span) => {
+                    let text = self.text(span);
+                    self.out.push_str(&text);
+                }
+                Expr::Raw(span) => self.out.push_str(&self.source[span.clone()]),
+                Expr::Math(math) => self.out.push_str(&self.source[math.tex.clone()]),
+                Expr::Error => self.out.push_str(&self.source[expr.range()]),
+            }
+        }
+    }
+
+    fn start_line(&mut self) {
+        if !self.out.is_empty() && !self.out.ends_with('\n') {
+            self.out.push('\n');
+        }
+    }
+
+    fn push_text(&mut self, span: &Span) {
+        let text = self.text(span);
+        push_escaped(&mut self.out, &text);
+    }
+
+    fn text(&self, span: &Span) -> Cow<'a, str> {
+        unescape(&self.source[span.clone()])
+    }
+}
+
+fn push_escaped(out: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\textbackslash{}"),
+            '~' => out.push_str("\\textasciitilde{}"),
+            '^' => out.push_str("\\textasciicircum{}"),
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                out.push('\\');
+                out.push(c);
+            }
+            _ => out.push(c),
+        }
+    }
+}
+
+// hyperref takes URLs mostly verbatim, only these need escaping
+fn push_escaped_url(out: &mut String, url: &str) {
+    for c in url.chars() {
+        if matches!(c, '#' | '%' | '\\' | '{' | '}') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+}
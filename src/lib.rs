@@ -0,0 +1,40 @@
+//! A parser for CaYaTeX, a markup language built from inline elements
+//! (`[bold text]`) and block elements (`{theorem| body}`).
+//!
+//! `Parser` turns a source into a tree of `Loc<Expr>`s, or into a stream of
+//! `Event`s for processing large documents without building the tree. Every
+//! node carries the byte range it was parsed from, which `LineIndex` converts
+//! into line and column positions and `Diagnostic` uses to render errors.
+//! `Parser::parse` wraps the tree in a `Document` whose nodes give access to
+//! names and text as slices of the source.
+//!
+//! The `html` and `latex` modules render a parsed document, with `mathml`
+//! converting math for HTML output that can't rely on a JavaScript math
+//! engine. `cst` keeps every byte of the source for tools that need to
+//! reproduce it exactly and `fmt` formats documents into a canonical layout.
+//! Passes over the tree can be written with the traits in `visit`; `schema`
+//! checks that a document only uses known elements in the right places and
+//! `refs` numbers elements and resolves references to their labels.
+
+pub mod cst;
+pub mod diagnostic;
+mod document;
+pub mod fmt;
+pub mod html;
+#[cfg(feature = "serde")]
+pub mod json;
+pub mod latex;
+pub mod line_index;
+pub mod mathml;
+mod parser;
+pub mod refs;
+pub mod schema;
+pub mod visit;
+
+pub use diagnostic::Diagnostic;
+pub use document::{Document, Node, OwnedDocument};
+pub use line_index::{LineCol, LineIndex};
+pub use parser::{
+    unescape, Element, ElementKind, Event, Expr, Loc, Math, ParseError, Parser, Span,
+    DEFAULT_RAW_ELEMENTS,
+};
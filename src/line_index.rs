@@ -0,0 +1,152 @@
+use crate::parser::Loc;
+use std::fmt::{self, Debug, Display};
+
+/// Converts between byte offsets into a source and 1-based line and column
+/// positions.
+pub struct LineIndex<'src> {
+    source: &'src str,
+    // Byte offset of the start of each line
+    line_starts: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineCol {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl<'src> LineIndex<'src> {
+    pub fn new(source: &'src str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(idx, _)| idx + 1))
+            .collect();
+
+        LineIndex {
+            source,
+            line_starts,
+        }
+    }
+
+    /// The line and column of `offset`, with the column counted in UTF-8
+    /// bytes. Offsets past the end of the source are clamped to it.
+    pub fn line_col(&self, offset: usize) -> LineCol {
+        let (line, line_start) = self.line_of(offset);
+        LineCol {
+            line,
+            col: offset.min(self.source.len()) - line_start + 1,
+        }
+    }
+
+    /// Like `line_col` but with the column counted in chars, as shown to
+    /// people in diagnostics.
+    pub fn line_col_chars(&self, offset: usize) -> LineCol {
+        let (line, line_start) = self.line_of(offset);
+        let col = self.source[line_start..offset.min(self.source.len())]
+            .chars()
+            .count();
+        LineCol { line, col: col + 1 }
+    }
+
+    /// Like `line_col` but with the column counted in UTF-16 code units, as
+    /// used by editors speaking LSP.
+    pub fn line_col_utf16(&self, offset: usize) -> LineCol {
+        let (line, line_start) = self.line_of(offset);
+        let col = self.source[line_start..offset.min(self.source.len())]
+            .chars()
+            .map(char::len_utf16)
+            .sum::<usize>();
+        LineCol { line, col: col + 1 }
+    }
+
+    /// The byte offset of a line and UTF-8 column. Returns `None` if the
+    /// position is outside the source or not on a char boundary.
+    pub fn offset(&self, line_col: LineCol) -> Option<usize> {
+        let line = self.raw_line(line_col.line)?;
+        let col = line_col.col.checked_sub(1)?;
+        if col <= line.len() && line.is_char_boundary(col) {
+            Some(self.line_starts[line_col.line - 1] + col)
+        } else {
+            None
+        }
+    }
+
+    /// The byte offset of a line and UTF-16 column. Returns `None` if the
+    /// position is outside the source or in the middle of a surrogate pair.
+    pub fn offset_utf16(&self, line_col: LineCol) -> Option<usize> {
+        let line = self.raw_line(line_col.line)?;
+        let mut remaining = line_col.col.checked_sub(1)?;
+        for (idx, c) in line.char_indices() {
+            if remaining == 0 {
+                return Some(self.line_starts[line_col.line - 1] + idx);
+            }
+            remaining = remaining.checked_sub(c.len_utf16())?;
+        }
+
+        if remaining == 0 {
+            Some(self.line_starts[line_col.line - 1] + line.len())
+        } else {
+            None
+        }
+    }
+
+    /// The text of a 1-based line, without its line ending.
+    pub fn line(&self, line: usize) -> Option<&'src str> {
+        let text = self.raw_line(line)?;
+        Some(text.strip_suffix('\r').unwrap_or(text))
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    // The text of a line including any `\r` before the `\n`
+    fn raw_line(&self, line: usize) -> Option<&'src str> {
+        let start = *self.line_starts.get(line.checked_sub(1)?)?;
+        let end = self
+            .line_starts
+            .get(line)
+            .map_or(self.source.len(), |&next| next - 1);
+        Some(&self.source[start..end])
+    }
+
+    fn line_of(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.source.len());
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(next_line) => next_line - 1,
+        };
+        (line + 1, self.line_starts[line])
+    }
+}
+
+/// Displays a located value prefixed with its position, e.g.
+/// `doc.cyt:12:7: expected |, received }`
+pub struct LocDisplay<'a, T: Debug> {
+    loc: &'a Loc<T>,
+    file_name: &'a str,
+    index: &'a LineIndex<'a>,
+}
+
+impl<T: Debug> Loc<T> {
+    pub fn display<'a>(&'a self, file_name: &'a str, index: &'a LineIndex) -> LocDisplay<'a, T> {
+        LocDisplay {
+            loc: self,
+            file_name,
+            index,
+        }
+    }
+}
+
+impl<'a, T: Debug + Display> Display for LocDisplay<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let LineCol { line, col } = self.index.line_col_chars(self.loc.range().start);
+        write!(
+            f,
+            "{}:{}:{}: {}",
+            self.file_name,
+            line,
+            col,
+            self.loc.inner()
+        )
+    }
+}
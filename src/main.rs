@@ -1,9 +1,371 @@
-use crate::parser::Parser;
+use cayatex::diagnostic::{apply_fixes, Fix, Level};
+use cayatex::fmt::{self, FormatOptions};
+use cayatex::html::{HtmlOptions, MathOutput};
+use cayatex::refs;
+use cayatex::schema::{self, ElementRegistry};
+use cayatex::{html, latex, mathml, Diagnostic, Expr, LineIndex, Loc, ParseError, Parser};
+use std::fs;
+use std::io::{self, IsTerminal, Read, Write};
+use std::process;
 
-mod parser;
+const USAGE: &str = "\
+usage: cayatex <command> [options] [file]
+
+commands:
+    parse     print the document tree
+    check     report every error in one or more documents, including unknown
+              or misplaced elements and undefined or duplicate labels
+    render    render a document, see --to
+    fmt       format a document
+
+options:
+    -o, --output <file>        write to <file> instead of stdout
+    --to <format>              output format for render: html (default) or
+                               latex
+    --math <tex|mathml>        with render --to html, leave math as TeX for
+                               MathJax or KaTeX (default) or convert it to
+                               MathML
+    --check                    with fmt, fail if the input isn't formatted
+    --fix                      with check, apply the fixes for errors that
+                               have one, such as misspelled element names.
+                               Files are fixed in place and stdin is written
+                               to the output
+    --json                     with parse, print the tree and any errors as
+                               JSON (needs the `serde` feature)
+    --width <columns>          with fmt, the width to wrap paragraphs to (80)
+    --color <auto|always|never>
+    -h, --help
+
+Input is read from stdin when no file or `-` is given.
+
+exit status:
+    0 on success, 1 if a document has errors or, with fmt --check, isn't
+    formatted, 2 on invalid usage and 3 if a file couldn't be read or written";
+
+// Exit codes
+const PARSE_FAILURE: i32 = 1;
+const USAGE_FAILURE: i32 = 2;
+const IO_FAILURE: i32 = 3;
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Parse,
+    Check,
+    Render,
+    Fmt,
+}
+
+struct Options {
+    command: Command,
+    inputs: Vec<String>,
+    output: Option<String>,
+    format: Option<String>,
+    math: Option<MathOutput>,
+    check: bool,
+    fix: bool,
+    json: bool,
+    width: usize,
+    color: bool,
+}
+
+struct Input {
+    name: String,
+    source: String,
+}
 
 fn main() {
-    let mut parser = Parser::new("hello world [bold");
-    let exprs = parser.parse_document();
-    println!("{:?}", exprs);
+    let options = parse_args(std::env::args().skip(1)).unwrap_or_else(|message| {
+        eprintln!("error: {}\n\n{}", message, USAGE);
+        process::exit(USAGE_FAILURE);
+    });
+
+    process::exit(run(&options));
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let command = match args.next().as_deref() {
+        Some("parse") => Command::Parse,
+        Some("check") => Command::Check,
+        Some("render") => Command::Render,
+        Some("fmt") => Command::Fmt,
+        Some("-h") | Some("--help") => {
+            println!("{}", USAGE);
+            process::exit(0);
+        }
+        Some(command) => return Err(format!("unknown command `{}`", command)),
+        None => return Err("no command given".to_string()),
+    };
+
+    let mut options = Options {
+        command,
+        inputs: Vec::new(),
+        output: None,
+        format: None,
+        math: None,
+        check: false,
+        fix: false,
+        json: false,
+        width: FormatOptions::default().width,
+        color: io::stderr().is_terminal(),
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .ok_or_else(|| format!("`{}` expects a value", name))
+        };
+        match arg.as_str() {
+            "-o" | "--output" => options.output = Some(value(&arg)?),
+            "--to" => options.format = Some(value(&arg)?),
+            "--math" => {
+                options.math = match value(&arg)?.as_str() {
+                    "tex" => Some(MathOutput::Delimiters),
+                    "mathml" => Some(MathOutput::MathMl),
+                    math => return Err(format!("unknown math output `{}`", math)),
+                }
+            }
+            "--check" => options.check = true,
+            "--fix" => options.fix = true,
+            "--json" => options.json = true,
+            "--width" => {
+                let width = value(&arg)?;
+                options.width = width
+                    .parse()
+                    .map_err(|_| format!("invalid width `{}`", width))?;
+            }
+            "--color" => {
+                options.color = match value(&arg)?.as_str() {
+                    "auto" => io::stderr().is_terminal(),
+                    "always" => true,
+                    "never" => false,
+                    color => return Err(format!("unknown color setting `{}`", color)),
+                }
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            "-" => options.inputs.push(arg),
+            _ if arg.starts_with('-') => return Err(format!("unknown option `{}`", arg)),
+            _ => options.inputs.push(arg),
+        }
+    }
+
+    if options.inputs.len() > 1 && options.command != Command::Check && !options.check {
+        return Err("only `check` and `fmt --check` accept more than one file".to_string());
+    }
+    if options.check && options.command != Command::Fmt {
+        return Err("`--check` is only valid with `fmt`".to_string());
+    }
+    if options.fix && options.command != Command::Check {
+        return Err("`--fix` is only valid with `check`".to_string());
+    }
+    if options.width != FormatOptions::default().width && options.command != Command::Fmt {
+        return Err("`--width` is only valid with `fmt`".to_string());
+    }
+    if options.json && options.command != Command::Parse {
+        return Err("`--json` is only valid with `parse`".to_string());
+    }
+    if options.json && !cfg!(feature = "serde") {
+        return Err("`--json` needs cayatex to be built with the `serde` feature".to_string());
+    }
+    if options.format.is_some() && options.command != Command::Render {
+        return Err("`--to` is only valid with `render`".to_string());
+    }
+    if options.math.is_some()
+        && (options.command != Command::Render
+            || options.format.as_deref().unwrap_or("html") != "html")
+    {
+        return Err("`--math` is only valid with `render --to html`".to_string());
+    }
+
+    Ok(options)
+}
+
+fn run(options: &Options) -> i32 {
+    let inputs = if options.inputs.is_empty() {
+        vec!["-".to_string()]
+    } else {
+        options.inputs.clone()
+    };
+
+    let mut status = 0;
+    for path in &inputs {
+        let input = match read_input(path) {
+            Ok(input) => input,
+            Err(err) => {
+                eprintln!("error: could not read {}: {}", path, err);
+                return IO_FAILURE;
+            }
+        };
+
+        let (exprs, errors) = Parser::new(&input.source).parse_document_recovering();
+        // `check` reports parse errors along with the rest
+        if options.command != Command::Check {
+            report(&input, &errors, options.color);
+        }
+        if !errors.is_empty() {
+            status = PARSE_FAILURE;
+            // The JSON output includes the errors, and tools consuming it
+            // still want the partial tree. `check` reports every error, so it
+            // goes on to validate the recovered tree.
+            if !options.json && options.command != Command::Check {
+                continue;
+            }
+        }
+
+        let output = match options.command {
+            Command::Parse if options.json => to_json(&input.source, &exprs, &errors),
+            Command::Parse => format!("{:#?}\n", exprs),
+            Command::Check => {
+                let mut diagnostics: Vec<Diagnostic> =
+                    errors.iter().map(Diagnostic::from).collect();
+                diagnostics.extend(check(&input.source, &exprs));
+                diagnostics.sort_by_key(|diagnostic| diagnostic.primary.span.start);
+                report_diagnostics(&input, &diagnostics, options.color);
+                if !options.fix {
+                    if !diagnostics.is_empty() {
+                        status = PARSE_FAILURE;
+                    }
+                    continue;
+                }
+
+                let fixes: Vec<Fix> = diagnostics
+                    .into_iter()
+                    .flat_map(|diagnostic| diagnostic.fixes)
+                    .collect();
+                let fixed = apply_fixes(&input.source, &fixes);
+                // The status is for the fixed document, since a fix could
+                // leave errors that weren't reported
+                let (fixed_exprs, errors) = Parser::new(&fixed).parse_document_recovering();
+                if !errors.is_empty() || !check(&fixed, &fixed_exprs).is_empty() {
+                    status = PARSE_FAILURE;
+                }
+                // Fixed stdin goes to the output, files are fixed in place
+                if path != "-" {
+                    if fixes.is_empty() {
+                        continue;
+                    }
+                    let plural = if fixes.len() == 1 { "" } else { "es" };
+                    eprintln!("{}: applied {} fix{}", input.name, fixes.len(), plural);
+                    if let Err(err) = fs::write(path, &fixed) {
+                        eprintln!("error: could not write {}: {}", path, err);
+                        return IO_FAILURE;
+                    }
+                    continue;
+                }
+                fixed
+            }
+            Command::Render => match options.format.as_deref().unwrap_or("html") {
+                "html" => {
+                    let html_options = HtmlOptions {
+                        math: options.math.unwrap_or(MathOutput::Delimiters),
+                    };
+                    if html_options.math == MathOutput::MathMl {
+                        let errors = mathml::check(&input.source, &exprs);
+                        report(&input, &errors, options.color);
+                    }
+                    html::render_with_options(&input.source, &exprs, &html_options)
+                }
+                "latex" => latex::render(&input.source, &exprs),
+                format => {
+                    eprintln!("error: unknown output format `{}`", format);
+                    return USAGE_FAILURE;
+                }
+            },
+            Command::Fmt => {
+                let format_options = FormatOptions {
+                    width: options.width,
+                    ..FormatOptions::default()
+                };
+                let formatted = fmt::format(&input.source, &exprs, &format_options);
+                if options.check {
+                    if formatted != input.source {
+                        eprintln!("{} is not formatted", input.name);
+                        status = PARSE_FAILURE;
+                    }
+                    continue;
+                }
+                formatted
+            }
+        };
+
+        if let Err(err) = write_output(options.output.as_deref(), &output) {
+            eprintln!("error: could not write output: {}", err);
+            return IO_FAILURE;
+        }
+    }
+
+    status
+}
+
+// The errors `check` reports besides parse errors, in source order
+fn check(source: &str, exprs: &[Loc<Expr>]) -> Vec<Diagnostic> {
+    let errors = schema::validate(source, exprs, &ElementRegistry::standard());
+    let (_, reference_errors) = refs::resolve(source, exprs);
+    let mut diagnostics: Vec<Diagnostic> = errors.iter().map(Diagnostic::from).collect();
+    diagnostics.extend(reference_errors.iter().map(Diagnostic::from));
+    diagnostics.sort_by_key(|diagnostic| diagnostic.primary.span.start);
+    diagnostics
+}
+
+#[cfg(feature = "serde")]
+fn to_json(source: &str, exprs: &[Loc<Expr>], errors: &[Loc<ParseError>]) -> String {
+    let mut json = cayatex::json::to_json(source, exprs, errors);
+    json.push('\n');
+    json
+}
+
+#[cfg(not(feature = "serde"))]
+fn to_json(_: &str, _: &[Loc<Expr>], _: &[Loc<ParseError>]) -> String {
+    unreachable!("`--json` is rejected without the `serde` feature")
+}
+
+fn report<'a, T>(input: &Input, errors: &'a [T], color: bool)
+where
+    Diagnostic: From<&'a T>,
+{
+    let diagnostics: Vec<Diagnostic> = errors.iter().map(Diagnostic::from).collect();
+    report_diagnostics(input, &diagnostics, color);
+}
+
+fn report_diagnostics(input: &Input, diagnostics: &[Diagnostic], color: bool) {
+    let index = LineIndex::new(&input.source);
+    let mut error_count = 0;
+    let mut warning_count = 0;
+    for diagnostic in diagnostics {
+        match diagnostic.level {
+            Level::Error => error_count += 1,
+            Level::Warning => warning_count += 1,
+        }
+        eprintln!("{}", diagnostic.render(&input.name, &index, color));
+    }
+    for (count, noun) in [(error_count, "error"), (warning_count, "warning")] {
+        if count > 0 {
+            let plural = if count == 1 { "" } else { "s" };
+            eprintln!("{}: {} {}{}", input.name, count, noun, plural);
+        }
+    }
+}
+
+fn read_input(path: &str) -> io::Result<Input> {
+    if path == "-" {
+        let mut source = String::new();
+        io::stdin().read_to_string(&mut source)?;
+        Ok(Input {
+            name: "<stdin>".to_string(),
+            source,
+        })
+    } else {
+        Ok(Input {
+            name: path.to_string(),
+            source: fs::read_to_string(path)?,
+        })
+    }
+}
+
+fn write_output(path: Option<&str>, output: &str) -> io::Result<()> {
+    match path {
+        Some(path) if path != "-" => fs::write(path, output),
+        _ => io::stdout().write_all(output.as_bytes()),
+    }
 }
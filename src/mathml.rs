@@ -0,0 +1,941 @@
+//! Converts the TeX inside math elements to presentation MathML, for HTML
+//! output that has to work without a JavaScript math engine.
+//!
+//! Only a subset of TeX is supported: letters, numbers and operators,
+//! groups, sub- and superscripts, `\frac`, `\sqrt`, Greek letters, common
+//! symbols and function names, `\left`/`\right`, `\text` and the font
+//! commands, and the `matrix` family of environments along with `cases` and
+//! `aligned`. Anything else is rendered as `<merror>` and reported with its
+//! location in the source.
+//!
+//! ```
+//! use cayatex::{mathml, Expr, Parser};
+//!
+//! let source = "$x^2$";
+//! let exprs = Parser::new(source).parse_document().unwrap();
+//! if let Expr::Math(math) = exprs[0].inner() {
+//!     let (markup, errors) = mathml::convert(source, math);
+//!     assert!(markup.contains("<msup><mi>x</mi><mn>2</mn></msup>"));
+//!     assert!(errors.is_empty());
+//! }
+//! ```
+
+use crate::parser::{Expr, Loc, Math, Span};
+use crate::visit::Visitor;
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MathError {
+    #[error("unsupported command `\\{}`", command)]
+    UnsupportedCommand { command: String },
+    #[error("unsupported environment `{}`", name)]
+    UnsupportedEnvironment { name: String },
+    /// A command or script without the argument it needs
+    #[error("`{}` expects an argument", command)]
+    MissingArgument { command: String },
+    #[error("right brace without matching left brace")]
+    UnmatchedRightBrace,
+    /// A group without its closing brace. `opener` points at the `{`.
+    #[error("group is never closed, expected }}")]
+    UnclosedGroup { opener: Span },
+    /// An environment without its `\end`. `opener` points at the `\begin`.
+    #[error("environment `{}` is never closed, expected \\end{{{}}}", name, name)]
+    UnclosedEnvironment { name: String, opener: Span },
+    #[error("expected \\end{{{}}}, found \\end{{{}}}", expected, found)]
+    MismatchedEnvironment { expected: String, found: String },
+    /// `&` or `\\` outside of a matrix
+    #[error("`{}` is only allowed inside a matrix", token)]
+    Misplaced { token: String },
+}
+
+/// Converts the TeX of a math element to a `<math>` element. Unsupported
+/// input is rendered as `<merror>` and reported, so the markup is usable
+/// even when there are errors.
+pub fn convert(source: &str, math: &Math) -> (String, Vec<Loc<MathError>>) {
+    let mut converter = Converter {
+        source,
+        idx: math.tex.start,
+        end: math.tex.end,
+        display: math.display,
+        variant: None,
+        errors: Vec::new(),
+    };
+    let (body, _) = converter.parse_row(Context::Top);
+
+    let display = if math.display { "block" } else { "inline" };
+    let markup = format!(
+        "<math xmlns=\"http://www.w3.org/1998/Math/MathML\" display=\"{}\">{}</math>",
+        display, body
+    );
+    (markup, converter.errors)
+}
+
+/// Converts every math element of a document and returns the errors, to
+/// report them before rendering.
+pub fn check(source: &str, exprs: &[Loc<Expr>]) -> Vec<Loc<MathError>> {
+    struct Checker<'a> {
+        source: &'a str,
+        errors: Vec<Loc<MathError>>,
+    }
+
+    impl Visitor for Checker<'_> {
+        fn visit_math(&mut self, _range: &Span, math: &Math) {
+            let (_, errors) = convert(self.source, math);
+            self.errors.extend(errors);
+        }
+    }
+
+    let mut checker = Checker {
+        source,
+        errors: Vec::new(),
+    };
+    checker.visit_exprs(exprs);
+    checker.errors
+}
+
+// What a row of atoms is nested in, which decides the tokens that end it
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Context {
+    Top,
+    Group,
+    Table,
+}
+
+// The token that ended a row
+#[derive(PartialEq, Eq)]
+enum Stop {
+    End,
+    RightBrace,
+    Ampersand,
+    NewRow,
+    // The name of the environment and the range of the `\end`
+    EndEnvironment(String, Span),
+}
+
+// An atom along with its scripts, which are only known after the atom has
+// been parsed
+struct Atom {
+    base: String,
+    sub: Option<String>,
+    sup: Option<String>,
+    // Whether scripts go above and below, as for `\sum` in display math
+    limits: bool,
+}
+
+impl Atom {
+    fn new(base: String) -> Self {
+        Atom {
+            base,
+            sub: None,
+            sup: None,
+            limits: false,
+        }
+    }
+
+    fn into_markup(self) -> String {
+        let (sub_tag, sup_tag, both_tag) = if self.limits {
+            ("munder", "mover", "munderover")
+        } else {
+            ("msub", "msup", "msubsup")
+        };
+        match (self.sub, self.sup) {
+            (None, None) => self.base,
+            (Some(sub), None) => format!("<{0}>{1}{2}</{0}>", sub_tag, self.base, sub),
+            (None, Some(sup)) => format!("<{0}>{1}{2}</{0}>", sup_tag, self.base, sup),
+            (Some(sub), Some(sup)) => {
+                format!("<{0}>{1}{2}{3}</{0}>", both_tag, self.base, sub, sup)
+            }
+        }
+    }
+}
+
+struct Converter<'a> {
+    source: &'a str,
+    idx: usize,
+    // The end of the TeX, which is usually before the end of the source
+    end: usize,
+    display: bool,
+    // Set by font commands such as `\mathbf` for the letters in their argument
+    variant: Option<&'static str>,
+    errors: Vec<Loc<MathError>>,
+}
+
+impl<'a> Converter<'a> {
+    fn peek(&self) -> Option<char> {
+        self.source[self.idx..self.end].chars().next()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.idx += c.len_utf8();
+        Some(c)
+    }
+
+    fn report(&mut self, range: Span, error: MathError) {
+        self.errors.push(Loc::new(range, error));
+    }
+
+    fn take_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.bump();
+        }
+    }
+
+    fn parse_row(&mut self, context: Context) -> (String, Stop) {
+        let mut atoms: Vec<Atom> = Vec::new();
+        let stop = loop {
+            self.take_whitespace();
+            let start_idx = self.idx;
+            let c = match self.peek() {
+                Some(c) => c,
+                None => break Stop::End,
+            };
+
+            match c {
+                '}' => {
+                    self.bump();
+                    if context == Context::Group {
+                        break Stop::RightBrace;
+                    }
+                    self.report(start_idx..self.idx, MathError::UnmatchedRightBrace);
+                }
+                '&' => {
+                    self.bump();
+                    if context == Context::Table {
+                        break Stop::Ampersand;
+                    }
+                    self.report_misplaced(start_idx, "&");
+                }
+                '^' | '_' => {
+                    self.bump();
+                    let script = self.parse_argument(&c.to_string());
+                    if atoms.is_empty() {
+                        atoms.push(Atom::new("<mrow></mrow>".to_string()));
+                    }
+                    let atom = atoms.last_mut().unwrap();
+                    let slot = if c == '^' {
+                        &mut atom.sup
+                    } else {
+                        &mut atom.sub
+                    };
+                    // A second script of the same kind starts a new atom, as
+                    // in `x^2^3`
+                    if slot.is_some() {
+                        let mut atom = Atom::new("<mrow></mrow>".to_string());
+                        if c == '^' {
+                            atom.sup = Some(script);
+                        } else {
+                            atom.sub = Some(script);
+                        }
+                        atoms.push(atom);
+                    } else {
+                        *slot = Some(script);
+                    }
+                }
+                '\\' if self.source[self.idx..self.end].starts_with("\\\\") => {
+                    self.idx += 2;
+                    if context == Context::Table {
+                        break Stop::NewRow;
+                    }
+                    self.report_misplaced(start_idx, "\\\\");
+                }
+                '\\' if self.at_end_command() => {
+                    self.parse_command();
+                    let environment = self.parse_environment_name("end");
+                    if context == Context::Table {
+                        break Stop::EndEnvironment(environment, start_idx..self.idx);
+                    }
+                    self.report_misplaced(start_idx, "\\end");
+                }
+                _ => {
+                    if let Some(atom) = self.parse_atom(false) {
+                        atoms.push(atom);
+                    }
+                }
+            }
+        };
+
+        let markup = atoms.into_iter().map(Atom::into_markup).collect();
+        (markup, stop)
+    }
+
+    fn at_end_command(&self) -> bool {
+        let rest = &self.source[self.idx..self.end];
+        rest.starts_with("\\end")
+            && !rest[4..]
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic())
+    }
+
+    fn report_misplaced(&mut self, start_idx: usize, token: &str) {
+        self.report(
+            start_idx..self.idx,
+            MathError::Misplaced {
+                token: token.to_string(),
+            },
+        );
+    }
+
+    // Parses the argument of a command or script, which is either a group or
+    // a single token, so `x^23` is `x^{2}3`
+    fn parse_argument(&mut self, command: &str) -> String {
+        self.take_whitespace();
+        match self.peek() {
+            Some('}') | Some('&') | Some('^') | Some('_') | None => {
+                self.report(
+                    self.idx..self.idx,
+                    MathError::MissingArgument {
+                        command: command.to_string(),
+                    },
+                );
+                "<mrow></mrow>".to_string()
+            }
+            _ => self
+                .parse_atom(true)
+                .map(Atom::into_markup)
+                .unwrap_or_default(),
+        }
+    }
+
+    // Parses a single atom. With `single`, a run of digits only yields its
+    // first digit. Returns `None` for input that produces no markup, such as
+    // a stray `\right`.
+    fn parse_atom(&mut self, single: bool) -> Option<Atom> {
+        let start_idx = self.idx;
+        let c = self.peek()?;
+        let base = match c {
+            '{' => {
+                self.bump();
+                self.parse_group(start_idx)
+            }
+            '\\' => return self.parse_command_atom(),
+            '0'..='9' => {
+                self.bump();
+                if !single {
+                    self.take_number();
+                }
+                element("mn", &self.source[start_idx..self.idx])
+            }
+            '\'' => {
+                self.bump();
+                element("mo", "\u{2032}")
+            }
+            '~' => {
+                self.bump();
+                element("mtext", "\u{a0}")
+            }
+            c if c.is_alphabetic() => {
+                self.bump();
+                self.letter(c)
+            }
+            c => {
+                self.bump();
+                element("mo", &c.to_string())
+            }
+        };
+
+        Some(Atom::new(base))
+    }
+
+    // Takes the rest of a number, including a decimal point followed by a
+    // digit
+    fn take_number(&mut self) {
+        loop {
+            let mut chars = self.source[self.idx..self.end].chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), _) if c.is_ascii_digit() => {}
+                (Some('.'), Some(next)) if next.is_ascii_digit() => {}
+                _ => return,
+            }
+            self.bump();
+        }
+    }
+
+    // Called after bumping a `{`
+    fn parse_group(&mut self, start_idx: usize) -> String {
+        let (body, stop) = self.parse_row(Context::Group);
+        if stop != Stop::RightBrace {
+            self.report(
+                self.idx..self.idx,
+                MathError::UnclosedGroup {
+                    opener: start_idx..(start_idx + 1),
+                },
+            );
+        }
+        format!("<mrow>{}</mrow>", body)
+    }
+
+    // Parses a `\` and the command name after it, which is either a run of
+    // letters or a single other character
+    fn parse_command(&mut self) -> (String, Span) {
+        let start_idx = self.idx;
+        self.bump();
+        match self.bump() {
+            Some(c) if c.is_ascii_alphabetic() => {
+                while self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+                    self.bump();
+                }
+            }
+            _ => {}
+        }
+
+        let name = self.source[(start_idx + 1)..self.idx].to_string();
+        (name, start_idx..self.idx)
+    }
+
+    fn parse_command_atom(&mut self) -> Option<Atom> {
+        let (name, range) = self.parse_command();
+        let command = format!("\\{}", name);
+
+        if let Some(letter) = greek(&name) {
+            // Upright capitals follow the TeX convention
+            let base = if letter.is_uppercase() {
+                format!("<mi mathvariant=\"normal\">{}</mi>", letter)
+            } else {
+                element("mi", &letter.to_string())
+            };
+            return Some(Atom::new(base));
+        }
+        if let Some(symbol) = operator(&name) {
+            return Some(Atom::new(element("mo", symbol)));
+        }
+        if let Some(symbol) = identifier(&name) {
+            return Some(Atom::new(element("mi", symbol)));
+        }
+        if let Some(symbol) = large_operator(&name) {
+            return Some(Atom {
+                limits: self.display && name != "int" && name != "oint",
+                ..Atom::new(element("mo", symbol))
+            });
+        }
+        if FUNCTIONS.contains(&name.as_str()) {
+            return Some(Atom {
+                limits: self.display && LIMIT_FUNCTIONS.contains(&name.as_str()),
+                ..Atom::new(element("mi", &name))
+            });
+        }
+        if let Some(width) = space(&name) {
+            return Some(Atom::new(format!("<mspace width=\"{}\"/>", width)));
+        }
+        if let Some(variant) = font(&name) {
+            let outer = self.variant.replace(variant);
+            let argument = self.parse_argument(&command);
+            self.variant = outer;
+            return Some(Atom::new(argument));
+        }
+
+        let base = match name.as_str() {
+            "frac" | "dfrac" | "tfrac" => {
+                let numerator = self.parse_argument(&command);
+                let denominator = self.parse_argument(&command);
+                format!("<mfrac>{}{}</mfrac>", numerator, denominator)
+            }
+            "binom" => {
+                let top = self.parse_argument(&command);
+                let bottom = self.parse_argument(&command);
+                format!(
+                    "<mrow><mo>(</mo><mfrac linethickness=\"0\">{}{}</mfrac><mo>)</mo></mrow>",
+                    top, bottom
+                )
+            }
+            "sqrt" => {
+                self.take_whitespace();
+                if self.peek() == Some('[') {
+                    let index = self.parse_optional_argument();
+                    let radicand = self.parse_argument(&command);
+                    format!("<mroot>{}{}</mroot>", radicand, index)
+                } else {
+                    let radicand = self.parse_argument(&command);
+                    format!("<msqrt>{}</msqrt>", radicand)
+                }
+            }
+            "text" | "mbox" | "operatorname" => {
+                let tag = if name == "operatorname" {
+                    "mi"
+                } else {
+                    "mtext"
+                };
+                let text = self.parse_text_argument(&command);
+                element(tag, &text)
+            }
+            "left" | "right" | "big" | "Big" | "bigg" | "Bigg" => {
+                let delimiter = self.parse_delimiter(&command);
+                if delimiter.is_empty() {
+                    return None;
+                }
+                format!("<mo stretchy=\"true\">{}</mo>", escape(&delimiter))
+            }
+            "begin" => self.parse_environment(range.start),
+            _ => {
+                self.report(
+                    range.clone(),
+                    MathError::UnsupportedCommand { command: name },
+                );
+                format!("<merror><mtext>{}</mtext></merror>", escape(&command))
+            }
+        };
+
+        Some(Atom::new(base))
+    }
+
+    fn letter(&self, c: char) -> String {
+        match self.variant {
+            Some(variant) => format!(
+                "<mi mathvariant=\"{}\">{}</mi>",
+                variant,
+                escape(&c.to_string())
+            ),
+            None => element("mi", &c.to_string()),
+        }
+    }
+
+    // Called with a `[` next
+    fn parse_optional_argument(&mut self) -> String {
+        let start_idx = self.idx;
+        self.bump();
+        let mut atoms = String::new();
+        loop {
+            self.take_whitespace();
+            match self.peek() {
+                Some(']') => {
+                    self.bump();
+                    break;
+                }
+                Some(_) => {
+                    if let Some(atom) = self.parse_atom(false) {
+                        atoms.push_str(&atom.into_markup());
+                    }
+                }
+                None => {
+                    self.report(
+                        self.idx..self.idx,
+                        MathError::UnclosedGroup {
+                            opener: start_idx..(start_idx + 1),
+                        },
+                    );
+                    break;
+                }
+            }
+        }
+        format!("<mrow>{}</mrow>", atoms)
+    }
+
+    // The argument of `\text` is text rather than math, so it's taken as
+    // written up to the matching brace
+    fn parse_text_argument(&mut self, command: &str) -> String {
+        self.take_whitespace();
+        let start_idx = self.idx;
+        if self.peek() != Some('{') {
+            self.report(
+                start_idx..start_idx,
+                MathError::MissingArgument {
+                    command: command.to_string(),
+                },
+            );
+            return String::new();
+        }
+        self.bump();
+
+        let mut depth = 0;
+        let mut text = String::new();
+        loop {
+            match self.bump() {
+                Some('}') if depth == 0 => return text,
+                Some('\\') => text.extend(self.bump()),
+                Some(c) => {
+                    match c {
+                        '{' => depth += 1,
+                        '}' => depth -= 1,
+                        _ => {}
+                    }
+                    text.push(c);
+                }
+                None => {
+                    self.report(
+                        self.idx..self.idx,
+                        MathError::UnclosedGroup {
+                            opener: start_idx..(start_idx + 1),
+                        },
+                    );
+                    return text;
+                }
+            }
+        }
+    }
+
+    // The delimiter after `\left` and friends. `.` is an invisible delimiter
+    // and yields an empty string.
+    fn parse_delimiter(&mut self, command: &str) -> String {
+        self.take_whitespace();
+        let start_idx = self.idx;
+        match self.peek() {
+            Some('\\') => {
+                let (name, range) = self.parse_command();
+                match name.as_str() {
+                    "{" | "}" => name,
+                    "|" => "\u{2016}".to_string(),
+                    "langle" => "\u{27e8}".to_string(),
+                    "rangle" => "\u{27e9}".to_string(),
+                    "lfloor" => "\u{230a}".to_string(),
+                    "rfloor" => "\u{230b}".to_string(),
+                    "lceil" => "\u{2308}".to_string(),
+                    "rceil" => "\u{2309}".to_string(),
+                    _ => {
+                        self.report(range, MathError::UnsupportedCommand { command: name });
+                        String::new()
+                    }
+                }
+            }
+            Some('.') => {
+                self.bump();
+                String::new()
+            }
+            Some(c) if !c.is_alphanumeric() && c != '{' && c != '}' => {
+                self.bump();
+                c.to_string()
+            }
+            _ => {
+                self.report(
+                    start_idx..start_idx,
+                    MathError::MissingArgument {
+                        command: command.to_string(),
+                    },
+                );
+                String::new()
+            }
+        }
+    }
+
+    // Parses the `{name}` after `\begin` or `\end`
+    fn parse_environment_name(&mut self, command: &str) -> String {
+        self.take_whitespace();
+        let start_idx = self.idx;
+        if self.peek() != Some('{') {
+            self.report(
+                start_idx..start_idx,
+                MathError::MissingArgument {
+                    command: format!("\\{}", command),
+                },
+            );
+            return String::new();
+        }
+        self.bump();
+        let name_start = self.idx;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '*')
+        {
+            self.bump();
+        }
+        let name = self.source[name_start..self.idx].to_string();
+        if self.peek() == Some('}') {
+            self.bump();
+        } else {
+            self.report(
+                self.idx..self.idx,
+                MathError::UnclosedGroup {
+                    opener: start_idx..(start_idx + 1),
+                },
+            );
+        }
+        name
+    }
+
+    // Called after parsing `\begin`
+    fn parse_environment(&mut self, start_idx: usize) -> String {
+        let name_start = self.idx;
+        let name = self.parse_environment_name("begin");
+        let (open, close, columnalign) = match name.as_str() {
+            "matrix" => ("", "", None),
+            "pmatrix" => ("(", ")", None),
+            "bmatrix" => ("[", "]", None),
+            "Bmatrix" => ("{", "}", None),
+            "vmatrix" => ("|", "|", None),
+            "Vmatrix" => ("\u{2016}", "\u{2016}", None),
+            "cases" => ("{", "", Some("left left")),
+            "aligned" => ("", "", Some("right left")),
+            // Already reported by `parse_environment_name`
+            "" => ("", "", None),
+            _ => {
+                self.report(
+                    name_start..self.idx,
+                    MathError::UnsupportedEnvironment { name: name.clone() },
+                );
+                ("", "", None)
+            }
+        };
+
+        let mut rows = Vec::new();
+        let mut row = Vec::new();
+        loop {
+            let (cell, stop) = self.parse_row(Context::Table);
+            row.push(cell);
+            match stop {
+                Stop::Ampersand => {}
+                Stop::NewRow => rows.push(std::mem::take(&mut row)),
+                Stop::EndEnvironment(..) | Stop::End | Stop::RightBrace => {
+                    match stop {
+                        Stop::EndEnvironment(found, range) if found != name => self.report(
+                            range,
+                            MathError::MismatchedEnvironment {
+                                expected: name.clone(),
+                                found,
+                            },
+                        ),
+                        Stop::End => self.report(
+                            self.idx..self.idx,
+                            MathError::UnclosedEnvironment {
+                                name: name.clone(),
+                                opener: start_idx..name_start,
+                            },
+                        ),
+                        _ => {}
+                    }
+                    // A trailing `\\` doesn't start another row
+                    if row.len() > 1 || !row[0].is_empty() {
+                        rows.push(row);
+                    }
+                    break;
+                }
+            }
+        }
+
+        let mut table = String::from("<mtable");
+        if let Some(columnalign) = columnalign {
+            table.push_str(" columnalign=\"");
+            table.push_str(columnalign);
+            table.push('"');
+        }
+        table.push('>');
+        for row in rows {
+            table.push_str("<mtr>");
+            for cell in row {
+                table.push_str("<mtd>");
+                table.push_str(&cell);
+                table.push_str("</mtd>");
+            }
+            table.push_str("</mtr>");
+        }
+        table.push_str("</mtable>");
+
+        let mut markup = String::from("<mrow>");
+        if !open.is_empty() {
+            markup.push_str(&element("mo", open));
+        }
+        markup.push_str(&table);
+        if !close.is_empty() {
+            markup.push_str(&element("mo", close));
+        }
+        markup.push_str("</mrow>");
+        markup
+    }
+}
+
+fn element(tag: &str, text: &str) -> String {
+    format!("<{0}>{1}</{0}>", tag, escape(text))
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn greek(name: &str) -> Option<char> {
+    let letter = match name {
+        "alpha" => 'α',
+        "beta" => 'β',
+        "gamma" => 'γ',
+        "delta" => 'δ',
+        "epsilon" => 'ϵ',
+        "varepsilon" => 'ε',
+        "zeta" => 'ζ',
+        "eta" => 'η',
+        "theta" => 'θ',
+        "vartheta" => 'ϑ',
+        "iota" => 'ι',
+        "kappa" => 'κ',
+        "lambda" => 'λ',
+        "mu" => 'μ',
+        "nu" => 'ν',
+        "xi" => 'ξ',
+        "pi" => 'π',
+        "varpi" => 'ϖ',
+        "rho" => 'ρ',
+        "varrho" => 'ϱ',
+        "sigma" => 'σ',
+        "varsigma" => 'ς',
+        "tau" => 'τ',
+        "upsilon" => 'υ',
+        "phi" => 'ϕ',
+        "varphi" => 'φ',
+        "chi" => 'χ',
+        "psi" => 'ψ',
+        "omega" => 'ω',
+        "Gamma" => 'Γ',
+        "Delta" => 'Δ',
+        "Theta" => 'Θ',
+        "Lambda" => 'Λ',
+        "Xi" => 'Ξ',
+        "Pi" => 'Π',
+        "Sigma" => 'Σ',
+        "Upsilon" => 'Υ',
+        "Phi" => 'Φ',
+        "Psi" => 'Ψ',
+        "Omega" => 'Ω',
+        _ => return None,
+    };
+    Some(letter)
+}
+
+fn operator(name: &str) -> Option<&'static str> {
+    let symbol = match name {
+        "cdot" => "\u{22c5}",
+        "times" => "×",
+        "div" => "÷",
+        "pm" => "±",
+        "mp" => "∓",
+        "ast" => "∗",
+        "circ" => "∘",
+        "le" | "leq" => "≤",
+        "ge" | "geq" => "≥",
+        "ne" | "neq" => "≠",
+        "ll" => "≪",
+        "gg" => "≫",
+        "approx" => "≈",
+        "equiv" => "≡",
+        "sim" => "∼",
+        "simeq" => "≃",
+        "cong" => "≅",
+        "propto" => "∝",
+        "in" => "∈",
+        "notin" => "∉",
+        "ni" => "∋",
+        "subset" => "⊂",
+        "subseteq" => "⊆",
+        "supset" => "⊃",
+        "supseteq" => "⊇",
+        "cup" => "∪",
+        "cap" => "∩",
+        "setminus" => "∖",
+        "wedge" | "land" => "∧",
+        "vee" | "lor" => "∨",
+        "neg" | "lnot" => "¬",
+        "oplus" => "⊕",
+        "otimes" => "⊗",
+        "to" | "rightarrow" => "→",
+        "leftarrow" | "gets" => "←",
+        "leftrightarrow" => "↔",
+        "Rightarrow" | "implies" => "⇒",
+        "Leftarrow" => "⇐",
+        "Leftrightarrow" | "iff" => "⇔",
+        "mapsto" => "↦",
+        "mid" => "∣",
+        "parallel" => "∥",
+        "perp" => "⊥",
+        "forall" => "∀",
+        "exists" => "∃",
+        "ldots" | "dots" => "…",
+        "cdots" => "⋯",
+        "vdots" => "⋮",
+        "ddots" => "⋱",
+        "langle" => "\u{27e8}",
+        "rangle" => "\u{27e9}",
+        "lfloor" => "\u{230a}",
+        "rfloor" => "\u{230b}",
+        "lceil" => "\u{2308}",
+        "rceil" => "\u{2309}",
+        "{" => "{",
+        "}" => "}",
+        "|" => "‖",
+        "%" => "%",
+        "&" => "&",
+        "#" => "#",
+        "$" => "$",
+        "_" => "_",
+        _ => return None,
+    };
+    Some(symbol)
+}
+
+fn identifier(name: &str) -> Option<&'static str> {
+    let symbol = match name {
+        "infty" => "∞",
+        "partial" => "∂",
+        "nabla" => "∇",
+        "emptyset" | "varnothing" => "∅",
+        "ell" => "ℓ",
+        "hbar" => "ℏ",
+        "Re" => "ℜ",
+        "Im" => "ℑ",
+        "aleph" => "ℵ",
+        _ => return None,
+    };
+    Some(symbol)
+}
+
+fn large_operator(name: &str) -> Option<&'static str> {
+    let symbol = match name {
+        "sum" => "∑",
+        "prod" => "∏",
+        "coprod" => "∐",
+        "int" => "∫",
+        "oint" => "∮",
+        "bigcup" => "⋃",
+        "bigcap" => "⋂",
+        "bigoplus" => "⨁",
+        "bigotimes" => "⨂",
+        _ => return None,
+    };
+    Some(symbol)
+}
+
+const FUNCTIONS: &[&str] = &[
+    "sin", "cos", "tan", "cot", "sec", "csc", "arcsin", "arccos", "arctan", "sinh", "cosh", "tanh",
+    "log", "ln", "lg", "exp", "lim", "liminf", "limsup", "max", "min", "sup", "inf", "det", "dim",
+    "ker", "deg", "gcd", "arg", "Pr",
+];
+
+// Functions whose subscripts go underneath in display math
+const LIMIT_FUNCTIONS: &[&str] = &[
+    "lim", "liminf", "limsup", "max", "min", "sup", "inf", "det", "gcd", "Pr",
+];
+
+fn space(name: &str) -> Option<&'static str> {
+    let width = match name {
+        "," => "0.1667em",
+        ":" | ">" => "0.2222em",
+        ";" => "0.2778em",
+        " " => "0.25em",
+        "quad" => "1em",
+        "qquad" => "2em",
+        "!" => "-0.1667em",
+        _ => return None,
+    };
+    Some(width)
+}
+
+fn font(name: &str) -> Option<&'static str> {
+    let variant = match name {
+        "mathrm" => "normal",
+        "mathbf" => "bold",
+        "mathit" => "italic",
+        "mathbb" => "double-struck",
+        "mathcal" => "script",
+        "mathfrak" => "fraktur",
+        "mathsf" => "sans-serif",
+        "mathtt" => "monospace",
+        _ => return None,
+    };
+    Some(variant)
+}
@@ -1,19 +1,130 @@
-use std::char;
-use std::fmt::Debug;
+use crate::document::Document;
+use std::borrow::Cow;
+use std::collections::{HashSet, VecDeque};
+use std::fmt::{self, Debug};
 use std::ops::Range;
 use thiserror::Error;
+use unicode_xid::UnicodeXID;
 
-pub struct Parser {
-    source: Vec<u8>,
+/// Parses a CaYaTeX document into a tree of `Expr`s.
+///
+/// ```
+/// use cayatex::{Expr, Parser};
+///
+/// let exprs = Parser::new("hello [bold world]").parse_document().unwrap();
+/// assert!(matches!(exprs[1].inner(), Expr::Inline { .. }));
+/// ```
+///
+/// The parser is also an iterator of `Event`s, which lets a document be
+/// processed without building the tree:
+///
+/// ```
+/// use cayatex::{Event, Parser};
+///
+/// let texts = Parser::new("hello [bold world]")
+///     .filter(|event| matches!(event, Event::Text(_)))
+///     .count();
+/// assert_eq!(texts, 2);
+/// ```
+///
+/// The bodies of raw elements aren't parsed, so brackets inside them don't
+/// need escaping as long as they're balanced. Any element can be made raw
+/// by doubling its brackets, in which case the body runs until the same
+/// number of closing brackets and doesn't need to be balanced:
+///
+/// ```
+/// use cayatex::{Expr, Parser};
+///
+/// let exprs = Parser::new("[code a[0]] {{code| } }}").parse_document().unwrap();
+/// assert!(matches!(exprs[0].inner(), Expr::Inline { body, .. } if matches!(body[0].inner(), Expr::Raw(_))));
+/// assert!(matches!(exprs[2].inner(), Expr::Block { body, .. } if matches!(body[0].inner(), Expr::Raw(_))));
+/// ```
+pub struct Parser<'src> {
+    source: &'src str,
     idx: usize,
+    raw_elements: HashSet<String>,
+    // The elements we're currently inside, innermost last
+    open: Vec<Element>,
+    // Events that have been parsed but not yet returned
+    queue: VecDeque<Event>,
 }
 
-#[derive(Debug)]
+/// An event produced by iterating over a `Parser`. Every `Start` is
+/// eventually followed by a matching `End`, even for unclosed elements.
+#[derive(Debug, Clone)]
+pub enum Event {
+    Start(Element),
+    End(Element),
+    Text(Span),
+    /// The body of a raw element
+    Raw(Span),
+    /// A math element, which has no body events
+    Math(Loc<Math>),
+    /// Source that doesn't belong to any element or text, such as a stray
+    /// closing bracket
+    Skipped(Span),
+    Error(Loc<ParseError>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ElementKind {
+    Inline,
+    Block,
+}
+
+impl fmt::Display for ElementKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ElementKind::Inline => f.write_str("inline"),
+            ElementKind::Block => f.write_str("block"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Element {
+    pub kind: ElementKind,
+    /// `None` if the name is invalid, in which case the element becomes an
+    /// `Expr::Error` in the tree
+    pub name: Option<Span>,
+    pub args: Vec<Span>,
+    /// For `Event::Start` the range of the header, from the opening bracket
+    /// to the start of the body. For `Event::End` the range of the whole
+    /// element.
+    pub range: Span,
+}
+
+/// A value along with the byte range of the source it was parsed from.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Loc<T: Debug> {
     range: Range<usize>,
     inner: T,
 }
 
+impl<T: Debug> Loc<T> {
+    pub fn new(range: Span, inner: T) -> Self {
+        Loc { range, inner }
+    }
+
+    pub fn range(&self) -> Span {
+        self.range.clone()
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn inner_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
 macro_rules! loc {
     ($start:expr, $end:expr, $inner:expr) => {
         Loc {
@@ -23,83 +134,319 @@ macro_rules! loc {
     };
 }
 
-type Span = Range<usize>;
+/// Inline math, written `[math tex]` or `$tex$`, or display math, written
+/// `{equation| tex}` or `$$tex$$`. The TeX isn't parsed; like the body of a
+/// raw element it only needs its brackets balanced, and within `$`
+/// delimiters a `$` can be escaped as `\$`. As with inline raw elements, a
+/// `|` only ends the header of `[math …]` after quoted or `key=value`
+/// arguments, so absolute values need no escaping:
+///
+/// ```
+/// use cayatex::{Expr, Parser};
+///
+/// let source = "[math |x| + 1]";
+/// let exprs = Parser::new(source).parse_document().unwrap();
+/// match exprs[0].inner() {
+///     Expr::Math(math) => assert_eq!(&source[math.tex.clone()], "|x| + 1"),
+///     expr => panic!("expected math, found {:?}", expr),
+/// }
+/// ```
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Math {
+    /// Whether the math is set on its own line
+    pub display: bool,
+    /// `None` for the `$` shorthands
+    pub name: Option<Span>,
+    pub args: Vec<Span>,
+    /// The value of a `label=` argument
+    pub label: Option<Span>,
+    pub tex: Span,
+}
+
+/// A byte range into the source. Spans always fall on char boundaries.
+pub type Span = Range<usize>;
+
+/// The elements whose bodies aren't parsed unless `Parser::raw_elements`
+/// says otherwise. A `|` only ends the header of an inline raw element when
+/// every argument before it is quoted or a `key=value` pair, so that
+/// `[code a || b]` keeps `a || b` as its body.
+pub const DEFAULT_RAW_ELEMENTS: &[&str] = &["code", "verbatim"];
 
-#[derive(Error, Debug)]
+const INLINE_MATH: &str = "math";
+const DISPLAY_MATH: &str = "equation";
+
+#[derive(Error, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ParseError {
-    #[error("right bracket without matching left bracket. You can escape by prefixing the bracket with `\\`")]
+    /// A `]` or `}` that doesn't close any element
+    #[error("right bracket without matching left bracket")]
     UnmatchedRightBracket,
+    /// The source ended in the middle of an element header or argument
     #[error("end of file reached, expected {}", expected)]
     EndOfFile { expected: String },
     #[error("expected {}, received {}", expected, received)]
     UnexpectedChar { expected: String, received: String },
+    /// An element without its closing bracket. The error is located where
+    /// the bracket was expected and `opener` points at the opening bracket.
+    #[error("element is never closed, expected {}", expected)]
+    UnclosedElement { opener: Span, expected: String },
+    /// A quoted argument in a block header without its closing quote. Quoted
+    /// arguments end at the end of the line or header at the latest, where
+    /// the error is located, and `opener` points at the opening quote.
+    #[error("quoted argument is never closed, expected \"")]
+    UnclosedQuote { opener: Span },
 }
 
-#[derive(Debug)]
+/// A node of the document tree. Names, arguments and text are stored as
+/// spans into the source; text and arguments may contain escape sequences,
+/// see `unescape`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Expr {
+    /// `[name body]` or `[name args | body]`
     Inline {
         name: Span,
         args: Vec<Span>,
         body: Vec<Loc<Expr>>,
     },
+    /// `{name args | body}`
     Block {
         name: Span,
         args: Vec<Span>,
         body: Vec<Loc<Expr>>,
     },
     Text(Span),
+    /// The body of a raw element, kept exactly as written
+    Raw(Span),
+    Math(Math),
+    /// Source that was skipped over while recovering from an error
+    Error,
 }
 
-impl Parser {
-    pub fn new<T: Into<Vec<u8>>>(source: T) -> Self {
+impl<'src> Parser<'src> {
+    pub fn new(source: &'src str) -> Self {
         Parser {
-            source: source.into(),
+            source,
             idx: 0,
+            raw_elements: DEFAULT_RAW_ELEMENTS
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            open: Vec::new(),
+            queue: VecDeque::new(),
         }
     }
 
-    fn peek(&mut self) -> Option<(usize, u8)> {
-        self.source.get(self.idx).map(|c| (self.idx, *c))
+    /// Sets the names of the elements whose bodies are kept verbatim,
+    /// replacing `DEFAULT_RAW_ELEMENTS`. Math elements are always raw.
+    pub fn raw_elements<I, S>(mut self, names: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.raw_elements = names.into_iter().map(Into::into).collect();
+        self
+    }
+
+    fn peek(&mut self) -> Option<(usize, char)> {
+        self.source[self.idx..]
+            .chars()
+            .next()
+            .map(|c| (self.idx, c))
     }
 
-    fn bump(&mut self) -> Option<(usize, u8)> {
-        if self.idx < self.source.len() {
-            let idx = self.idx;
-            self.idx += 1;
-            Some((idx, self.source[idx]))
+    fn bump(&mut self) -> Option<(usize, char)> {
+        let (idx, c) = self.peek()?;
+        self.idx += c.len_utf8();
+        Some((idx, c))
+    }
+
+    /// Parses the whole document into a `Document` borrowing the source.
+    pub fn parse(self) -> Result<Document<'src>, Loc<ParseError>> {
+        let source = self.source;
+        let exprs = self.parse_document()?;
+        Ok(Document::new(source, exprs))
+    }
+
+    pub fn parse_document(self) -> Result<Vec<Loc<Expr>>, Loc<ParseError>> {
+        let (exprs, mut errors) = self.parse_document_recovering();
+        if errors.is_empty() {
+            Ok(exprs)
         } else {
-            None
+            Err(errors.remove(0))
         }
     }
 
-    pub fn parse_document(mut self) -> Result<Vec<Loc<Expr>>, Loc<ParseError>> {
+    /// Parses the whole document, recovering from errors instead of stopping
+    /// at the first one. Skipped source is represented by `Expr::Error`.
+    pub fn parse_document_recovering(self) -> (Vec<Loc<Expr>>, Vec<Loc<ParseError>>) {
         let mut exprs = Vec::new();
-        let mut start_idx: usize = 0;
-        while let Some((idx, c)) = self.bump() {
+        let mut errors = Vec::new();
+        // The bodies of the enclosing elements that we're still building
+        let mut parents = Vec::new();
+        for event in self {
+            match event {
+                Event::Start(_) => parents.push(std::mem::take(&mut exprs)),
+                Event::End(element) => {
+                    let body = std::mem::replace(&mut exprs, parents.pop().unwrap());
+                    let Element {
+                        kind,
+                        name,
+                        args,
+                        range,
+                    } = element;
+                    let expr = match (kind, name) {
+                        (ElementKind::Inline, Some(name)) => Expr::Inline { name, args, body },
+                        (ElementKind::Block, Some(name)) => Expr::Block { name, args, body },
+                        (_, None) => Expr::Error,
+                    };
+                    exprs.push(Loc::new(range, expr));
+                }
+                Event::Text(span) => exprs.push(Loc::new(span.clone(), Expr::Text(span))),
+                Event::Raw(span) => exprs.push(Loc::new(span.clone(), Expr::Raw(span))),
+                Event::Math(math) => {
+                    let range = math.range();
+                    exprs.push(Loc::new(range, Expr::Math(math.into_inner())));
+                }
+                Event::Skipped(span) => exprs.push(Loc::new(span, Expr::Error)),
+                Event::Error(error) => errors.push(error),
+            }
+        }
+
+        (exprs, errors)
+    }
+
+    fn report(&mut self, error: Loc<ParseError>) {
+        self.queue.push_back(Event::Error(error));
+    }
+
+    fn next_event(&mut self) -> Option<Event> {
+        let text_start = self.idx;
+        while let Some((_, c)) = self.peek() {
             match c {
-                // Slight repetition here. If necessary will refactor
-                b'[' => {
-                    exprs.push(loc!(start_idx, idx, Expr::Text(start_idx..idx)));
-                    let inline_expr = self.parse_inline(idx)?;
-                    start_idx = inline_expr.range.end + 1;
-                    exprs.push(inline_expr);
+                '[' | '{' | ']' | '}' | '$' => break,
+                '\\' => {
+                    self.bump();
+                    self.take_escaped();
                 }
-                b'{' => {
-                    exprs.push(loc!(start_idx, idx, Expr::Text(start_idx..idx)));
-                    let block_expr = self.parse_block(idx)?;
-                    start_idx = block_expr.range.end + 1;
-                    exprs.push(block_expr);
+                _ => {
+                    self.bump();
                 }
-                b']' | b'}' => return Err(loc!(idx, idx, ParseError::UnmatchedRightBracket)),
-                _ => {}
             }
         }
-        Ok(exprs)
+        if self.idx > text_start {
+            return Some(Event::Text(text_start..self.idx));
+        }
+
+        let (idx, c) = match self.bump() {
+            Some(next) => next,
+            None => {
+                // Close everything that's still open at the end of the source
+                let element = self.open.pop()?;
+                self.report_unclosed(&element, self.idx);
+                return Some(self.end_element(element));
+            }
+        };
+
+        match c {
+            '$' => self.parse_math_shorthand(idx),
+            '[' | '{' => {
+                // Doubled brackets open a fenced raw element
+                let mut fence = 1;
+                while self.peek().map(|(_, next)| next) == Some(c) {
+                    self.bump();
+                    fence += 1;
+                }
+                if c == '[' {
+                    self.parse_inline_header(idx, fence);
+                } else {
+                    self.parse_block_header(idx, fence);
+                }
+            }
+            _ => {
+                let innermost = self
+                    .open
+                    .last()
+                    .map(|element| closing_bracket(element.kind));
+                if innermost == Some(c) {
+                    let element = self.open.pop().unwrap();
+                    return Some(self.end_element(element));
+                }
+
+                // If the bracket closes an element further out, we assume the
+                // current one is missing its closing bracket
+                if self
+                    .open
+                    .iter()
+                    .any(|element| closing_bracket(element.kind) == c)
+                {
+                    self.idx = idx;
+                    let element = self.open.pop().unwrap();
+                    self.report_unclosed(&element, idx);
+                    return Some(self.end_element(element));
+                }
+
+                self.report(loc!(idx, idx + 1, ParseError::UnmatchedRightBracket));
+                self.queue.push_back(Event::Skipped(idx..(idx + 1)));
+            }
+        }
+
+        self.queue.pop_front()
+    }
+
+    fn end_element(&mut self, mut element: Element) -> Event {
+        element.range.end = self.idx;
+        let end = Event::End(element);
+        // Any errors found while closing the element come first
+        if self.queue.is_empty() {
+            end
+        } else {
+            self.queue.push_back(end);
+            self.queue.pop_front().unwrap()
+        }
+    }
+
+    fn report_unclosed(&mut self, element: &Element, idx: usize) {
+        let opener_idx = element.range.start;
+        self.report(loc!(
+            idx,
+            idx,
+            ParseError::UnclosedElement {
+                opener: opener_idx..(opener_idx + 1),
+                expected: closing_bracket(element.kind).to_string()
+            }
+        ));
+    }
+
+    // Called after bumping a `\`. If the next character can be escaped we
+    // consume it so that it's treated as text.
+    fn take_escaped(&mut self) {
+        if let Some((_, c)) = self.peek() {
+            if is_escapable(c) {
+                self.bump();
+            }
+        }
+    }
+
+    // Skips spaces and at most one line break, so that a raw block body
+    // starting on the line after its header keeps its indentation
+    fn take_line_break(&mut self) {
+        while let Some((_, c)) = self.peek() {
+            if c == '\n' {
+                self.bump();
+                return;
+            } else if c.is_whitespace() {
+                self.bump();
+            } else {
+                return;
+            }
+        }
     }
 
     fn take_whitespace(&mut self) {
         while let Some((_, c)) = self.peek() {
-            if c.is_ascii_whitespace() {
+            if c.is_whitespace() {
                 self.bump();
             } else {
                 return;
@@ -107,67 +454,316 @@ impl Parser {
         }
     }
 
-    fn expect_char(&mut self, expected_char: u8) -> Result<(), Loc<ParseError>> {
-        let (idx, c) = self.bump().ok_or_else(|| {
+    fn expect_char(&mut self, expected_char: char) -> Result<(), Loc<ParseError>> {
+        let (idx, c) = self.peek().ok_or_else(|| {
             loc!(
-                self.source.len() - 1,
-                self.source.len() - 1,
+                self.source.len(),
+                self.source.len(),
                 ParseError::EndOfFile {
-                    expected: char::from_digit(expected_char as u32, 10)
-                        .unwrap()
-                        .to_string(),
+                    expected: expected_char.to_string(),
                 }
             )
         })?;
 
         if c == expected_char {
+            self.bump();
             Ok(())
         } else {
             Err(loc!(
                 idx,
-                idx,
+                idx + c.len_utf8(),
                 ParseError::UnexpectedChar {
-                    expected: (expected_char as char).to_string(),
-                    received: (c as char).to_string(),
+                    expected: expected_char.to_string(),
+                    received: c.to_string(),
                 }
             ))
         }
     }
 
-    fn parse_inline(&mut self, start_idx: usize) -> Result<Loc<Expr>, Loc<ParseError>> {
+    fn parse_inline_header(&mut self, start_idx: usize, fence: usize) {
         self.take_whitespace();
-        let name = self.parse_name()?;
+        let name = self.parse_name().map_err(|err| self.report(err)).ok();
         self.take_whitespace();
 
-        Ok(loc!(
-            start_idx,
-            name.end,
-            Expr::Inline {
-                name,
-                args: Vec::new(),
-                body: Vec::new()
+        // Arguments are optional for inline elements, so if the header isn't
+        // terminated by a `|` we treat everything after the name as the body
+        let body_idx = self.idx;
+        let math = name
+            .as_ref()
+            .is_some_and(|name| &self.source[name.clone()] == INLINE_MATH);
+        let raw = math || self.is_raw(name.as_ref(), fence);
+        let args = match self.parse_args(ElementKind::Inline) {
+            Ok(args)
+                if self.peek().map(|(_, c)| c) == Some('|')
+                    && (!raw || self.are_options(&args)) =>
+            {
+                self.bump();
+                self.take_whitespace();
+                args
             }
-        ))
+            _ => {
+                self.idx = body_idx;
+                Vec::new()
+            }
+        };
+
+        self.start_element(ElementKind::Inline, start_idx, fence, name, args);
     }
 
-    fn parse_block(&mut self, start_idx: usize) -> Result<Loc<Expr>, Loc<ParseError>> {
+    fn parse_block_header(&mut self, start_idx: usize, fence: usize) {
         self.take_whitespace();
-        let name = self.parse_name()?;
+        let name = self.parse_name().map_err(|err| self.report(err)).ok();
         self.take_whitespace();
-        self.expect_char(b'|')?;
-        Ok(loc!(
-            start_idx,
-            name.end,
-            Expr::Block {
-                name,
-                args: Vec::new(),
-                body: Vec::new()
+        let args = match self.parse_args(ElementKind::Block) {
+            Ok(args) => args,
+            Err(err) => {
+                self.report(err);
+                // The header is already reported as broken
+                if self.peek().map(|(_, c)| c) == Some('|') {
+                    self.bump();
+                }
+                self.start_element(ElementKind::Block, start_idx, fence, name, Vec::new());
+                return;
+            }
+        };
+        // A missing `|` doesn't lose us any source, so we keep the block
+        if let Err(err) = self.expect_char('|') {
+            self.report(err);
+        }
+
+        self.start_element(ElementKind::Block, start_idx, fence, name, args);
+    }
+
+    fn start_element(
+        &mut self,
+        kind: ElementKind,
+        start_idx: usize,
+        fence: usize,
+        name: Option<Span>,
+        args: Vec<Span>,
+    ) {
+        let name_str = name.as_ref().map(|name| &self.source[name.clone()]);
+        let math = matches!(
+            (kind, name_str),
+            (ElementKind::Inline, Some(INLINE_MATH)) | (ElementKind::Block, Some(DISPLAY_MATH))
+        );
+        let raw = math || self.is_raw(name.as_ref(), fence);
+        if kind == ElementKind::Block {
+            if raw {
+                self.take_line_break();
+            } else {
+                self.take_whitespace();
+            }
+        }
+
+        let mut element = Element {
+            kind,
+            name,
+            args,
+            range: start_idx..self.idx,
+        };
+        if !raw {
+            self.queue.push_back(Event::Start(element.clone()));
+            self.open.push(element);
+            return;
+        }
+
+        let closer = closing_bracket(kind).to_string().repeat(fence);
+        let body_len = self.raw_body_len(kind, fence);
+        let body = self.take_raw(start_idx..(start_idx + fence), &closer, body_len);
+        element.range.end = self.idx;
+        if math {
+            let label = label(self.source, &element.args);
+            let math = Math {
+                display: kind == ElementKind::Block,
+                name: element.name,
+                args: element.args,
+                label,
+                tex: body,
+            };
+            self.queue
+                .push_back(Event::Math(Loc::new(element.range, math)));
+            return;
+        }
+
+        let header = element.range.start..body.start;
+        self.queue.push_back(Event::Start(Element {
+            range: header,
+            ..element.clone()
+        }));
+        if !body.is_empty() {
+            self.queue.push_back(Event::Raw(body));
+        }
+        self.queue.push_back(Event::End(element));
+    }
+
+    fn is_raw(&self, name: Option<&Span>, fence: usize) -> bool {
+        fence > 1 || name.is_some_and(|name| self.raw_elements.contains(&self.source[name.clone()]))
+    }
+
+    // Whether arguments are all quoted or `key=value` pairs, which a verbatim
+    // body is unlikely to start with
+    fn are_options(&self, args: &[Span]) -> bool {
+        !args.is_empty()
+            && args.iter().all(|arg| {
+                self.source.as_bytes()[arg.start - 1] == b'"'
+                    || self.source[arg.clone()].contains('=')
+            })
+    }
+
+    // Raw bodies run until the closing bracket that balances the opening one,
+    // or for fenced elements until as many closing brackets as there were
+    // opening ones
+    fn raw_body_len(&self, kind: ElementKind, fence: usize) -> Option<usize> {
+        let closer = closing_bracket(kind);
+        let opener = if closer == ']' { '[' } else { '{' };
+        let body = &self.source[self.idx..];
+        if fence > 1 {
+            return body.find(&closer.to_string().repeat(fence));
+        }
+
+        let mut depth = 0usize;
+        body.char_indices().find_map(|(idx, c)| {
+            if c == opener {
+                depth += 1;
+            } else if c == closer {
+                if depth == 0 {
+                    return Some(idx);
+                }
+                depth -= 1;
+            }
+            None
+        })
+    }
+
+    // `$…$` and `$$…$$` run until the next unescaped `closer`
+    fn math_body_len(&self, closer: &str) -> Option<usize> {
+        let body = &self.source[self.idx..];
+        let mut chars = body.char_indices();
+        while let Some((idx, c)) = chars.next() {
+            if c == '\\' {
+                chars.next();
+            } else if body[idx..].starts_with(closer) {
+                return Some(idx);
+            }
+        }
+        None
+    }
+
+    // Consumes a verbatim body of `body_len` bytes along with its `closer`, or
+    // the rest of the source if there's no closer, and returns the body
+    fn take_raw(&mut self, opener: Span, closer: &str, body_len: Option<usize>) -> Span {
+        let body_start = self.idx;
+        let body_end = body_len.map_or(self.source.len(), |len| body_start + len);
+        self.idx = body_end;
+        match body_len {
+            Some(_) => self.idx += closer.len(),
+            None => self.report(loc!(
+                body_end,
+                body_end,
+                ParseError::UnclosedElement {
+                    opener,
+                    expected: closer.to_string()
+                }
+            )),
+        }
+
+        body_start..body_end
+    }
+
+    // Called after bumping a `$`
+    fn parse_math_shorthand(&mut self, start_idx: usize) {
+        let display = self.peek().map(|(_, c)| c) == Some('$');
+        let delimiter = if display {
+            self.bump();
+            "$$"
+        } else {
+            "$"
+        };
+
+        let body_len = self.math_body_len(delimiter);
+        let tex = self.take_raw(start_idx..self.idx, delimiter, body_len);
+        let math = Math {
+            display,
+            name: None,
+            args: Vec::new(),
+            label: None,
+            tex,
+        };
+        self.queue
+            .push_back(Event::Math(Loc::new(start_idx..self.idx, math)));
+    }
+
+    // Arguments sit between the element name and the `|` and are separated by
+    // whitespace. An argument is either a bare word such as `label=pyth` or a
+    // double quoted string, in which case the span excludes the quotes.
+    fn parse_args(&mut self, kind: ElementKind) -> Result<Vec<Span>, Loc<ParseError>> {
+        let mut args = Vec::new();
+        while let Some((idx, c)) = self.peek() {
+            match c {
+                '|' | '[' | ']' | '{' | '}' => break,
+                '"' => {
+                    self.bump();
+                    args.push(self.parse_quoted_arg(kind, idx + 1)?);
+                }
+                _ => {
+                    while let Some((_, c)) = self.peek() {
+                        if c == '\\' {
+                            self.bump();
+                            self.take_escaped();
+                        } else if is_arg_char(c) {
+                            self.bump();
+                        } else {
+                            break;
+                        }
+                    }
+                    args.push(idx..self.idx);
+                }
+            }
+            self.take_whitespace();
+        }
+
+        Ok(args)
+    }
+
+    // A missing closing quote mustn't swallow the rest of the document, so
+    // the argument stops at anything that ends the header, and in block
+    // headers at a line break. An inline header that fails to parse is read
+    // as the body instead, where line breaks are only whitespace.
+    fn parse_quoted_arg(
+        &mut self,
+        kind: ElementKind,
+        start_idx: usize,
+    ) -> Result<Span, Loc<ParseError>> {
+        while let Some((idx, c)) = self.peek() {
+            match c {
+                '\n' if kind == ElementKind::Block => break,
+                '"' => {
+                    self.bump();
+                    return Ok(start_idx..idx);
+                }
+                '|' | '}' => break,
+                '\\' => {
+                    self.bump();
+                    self.take_escaped();
+                }
+                _ => {
+                    self.bump();
+                }
+            }
+        }
+
+        Err(loc!(
+            self.idx,
+            self.idx,
+            ParseError::UnclosedQuote {
+                opener: (start_idx - 1)..start_idx
             }
         ))
     }
 
     fn parse_name(&mut self) -> Result<Span, Loc<ParseError>> {
-        let (start_idx, c) = self.bump().ok_or_else(|| {
+        let (start_idx, c) = self.peek().ok_or_else(|| {
             loc!(
                 self.source.len(),
                 self.source.len(),
@@ -177,25 +773,106 @@ impl Parser {
             )
         })?;
 
-        if !c.is_ascii_alphabetic() {
+        if !UnicodeXID::is_xid_start(c) {
             return Err(loc!(
                 start_idx,
-                start_idx,
+                start_idx + c.len_utf8(),
                 ParseError::UnexpectedChar {
                     expected: "letter".to_string(),
                     received: c.to_string()
                 }
             ));
         }
+        self.bump();
 
         while let Some((idx, c)) = self.peek() {
-            if c.is_ascii_alphanumeric() {
+            if UnicodeXID::is_xid_continue(c) {
                 self.bump();
             } else {
                 return Ok(start_idx..idx);
             }
         }
 
-        Ok((start_idx)..(self.source.len() - 1))
+        Ok(start_idx..self.source.len())
     }
 }
+
+impl<'src> Iterator for Parser<'src> {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        match self.queue.pop_front() {
+            Some(event) => Some(event),
+            None => self.next_event(),
+        }
+    }
+}
+
+fn closing_bracket(kind: ElementKind) -> char {
+    match kind {
+        ElementKind::Inline => ']',
+        ElementKind::Block => '}',
+    }
+}
+
+fn is_escapable(c: char) -> bool {
+    matches!(c, '[' | ']' | '{' | '}' | '|' | '"' | '$' | '\\')
+}
+
+/// Removes the backslashes from escape sequences in text or arguments. A
+/// backslash before any other character is kept as is.
+pub fn unescape(text: &str) -> Cow<'_, str> {
+    if !text.contains('\\') {
+        return Cow::Borrowed(text);
+    }
+
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match chars.peek() {
+            Some(&next) if c == '\\' && is_escapable(next) => {
+                unescaped.push(next);
+                chars.next();
+            }
+            _ => unescaped.push(c),
+        }
+    }
+
+    Cow::Owned(unescaped)
+}
+
+/// The source of an inline element's arguments up to its body, including the
+/// `|` and any quotes, or `None` if it has no arguments. Renderers show this
+/// as text for elements that don't take arguments, since a `|` in the body of
+/// `[bold a | b]` is easily written without meaning to start one.
+pub(crate) fn args_text<'a>(source: &'a str, name: &Span, args: &[Span]) -> Option<&'a str> {
+    let last = args.last()?;
+    let separator = last.end + source[last.end..].find('|')?;
+    let after = &source[(separator + 1)..];
+    let end = source.len() - after.trim_start().len();
+    Some(source[name.end..end].trim_start())
+}
+
+/// The arguments other than a `label=` argument
+pub(crate) fn positional_args(source: &str, args: &[Span]) -> Vec<Span> {
+    match label(source, args) {
+        Some(label) => args
+            .iter()
+            .filter(|arg| arg.end != label.end)
+            .cloned()
+            .collect(),
+        None => args.to_vec(),
+    }
+}
+
+/// Finds the value of a `label=` argument
+pub(crate) fn label(source: &str, args: &[Span]) -> Option<Span> {
+    const KEY: &str = "label=";
+    args.iter()
+        .find(|&arg| source[arg.clone()].starts_with(KEY))
+        .map(|arg| (arg.start + KEY.len())..arg.end)
+}
+
+fn is_arg_char(c: char) -> bool {
+    !c.is_whitespace() && !matches!(c, '|' | '[' | ']' | '{' | '}' | '"')
+}
@@ -0,0 +1,234 @@
+//! Labels, automatic numbering and cross-references.
+//!
+//! A block takes a label as a `label=` argument and is referenced with
+//! `[ref label]`. `resolve` numbers blocks with a counter per element name,
+//! in document order, so the second theorem is Theorem 2 however many
+//! lemmas come before it. Theorem-like blocks, sections and named equations
+//! are always numbered; any other block is numbered among the blocks of its
+//! name that have a label. Labels can't contain any of
+//! `LABEL_SPECIAL_CHARS`, so that they can be used as they are in LaTeX.
+//!
+//! ```
+//! use cayatex::refs;
+//! use cayatex::Parser;
+//!
+//! let source = "{lemma| a} {theorem label=pyth| b} See [ref pyth].";
+//! let exprs = Parser::new(source).parse_document().unwrap();
+//! let (references, errors) = refs::resolve(source, &exprs);
+//! assert_eq!(references.get("pyth").unwrap().text(), "Theorem 1");
+//! assert!(errors.is_empty());
+//! ```
+
+use crate::parser::{label, unescape, Expr, Loc, Math, Span};
+use crate::visit::Visitor;
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// The name of the inline element that references a label
+pub const REF: &str = "ref";
+
+/// Characters that can't be used in labels since LaTeX would read them as
+/// markup inside `\label` and `\ref`
+pub const LABEL_SPECIAL_CHARS: &str = "\\{}%#~$&^";
+
+/// Elements that are numbered whether or not they have a label, as they are
+/// in LaTeX
+pub const NUMBERED: &[&str] = &[
+    "theorem",
+    "lemma",
+    "corollary",
+    "definition",
+    "example",
+    "section",
+    "equation",
+];
+
+/// The numbers and labels of a document
+#[derive(Debug, Clone, Default)]
+pub struct References {
+    targets: HashMap<String, Target>,
+    // Numbers by the start of the numbered element
+    numbers: HashMap<usize, usize>,
+}
+
+/// A labelled element
+#[derive(Debug, Clone)]
+pub struct Target {
+    /// The name of the element, such as `theorem`
+    pub name: String,
+    pub number: usize,
+    /// The range of the label
+    pub range: Span,
+}
+
+#[derive(Error, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ReferenceError {
+    #[error("undefined label `{}`", label)]
+    UndefinedLabel { label: String },
+    /// A label used by more than one element. `first` points at the first
+    /// use, which references resolve to.
+    #[error("label `{}` is defined more than once", label)]
+    DuplicateLabel { label: String, first: Span },
+    #[error("reference without a label")]
+    EmptyReference,
+    /// A label containing one of `LABEL_SPECIAL_CHARS`. The element is still
+    /// numbered but can't be referenced.
+    #[error("label `{}` contains a character that can't be used in labels", label)]
+    InvalidLabel { label: String },
+}
+
+impl References {
+    pub fn get(&self, label: &str) -> Option<&Target> {
+        self.targets.get(label)
+    }
+
+    /// The number of the element starting at `range`, if it's numbered
+    pub fn number(&self, range: &Span) -> Option<usize> {
+        self.numbers.get(&range.start).copied()
+    }
+}
+
+impl Target {
+    /// How references to the target read, such as `Theorem 3`
+    pub fn text(&self) -> String {
+        format!("{} {}", display_name(&self.name), self.number)
+    }
+}
+
+/// The name of a numbered element as it's shown with its number, such as
+/// `Theorem` for `theorem`
+pub fn display_name(name: &str) -> String {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Whether `label` can be used as a label, see `LABEL_SPECIAL_CHARS`
+pub fn is_valid_label(label: &str) -> bool {
+    !label.contains(|c| LABEL_SPECIAL_CHARS.contains(c))
+}
+
+/// The label of a `[ref label]` element, or `None` if `name` isn't `ref`.
+/// The label is the text of the body with escapes removed and surrounding
+/// whitespace trimmed.
+pub fn reference_label(source: &str, name: &Span, body: &[Loc<Expr>]) -> Option<String> {
+    if &source[name.clone()] != REF {
+        return None;
+    }
+
+    let mut label = String::new();
+    for expr in body {
+        if let Expr::Text(span) = expr.inner() {
+            label.push_str(&unescape(&source[span.clone()]));
+        }
+    }
+    Some(label.trim().to_string())
+}
+
+/// Numbers the elements of a document and resolves its references. Errors
+/// for undefined labels are located at the `[ref]` element and errors for
+/// duplicate labels at the second use of the label.
+pub fn resolve(source: &str, exprs: &[Loc<Expr>]) -> (References, Vec<Loc<ReferenceError>>) {
+    let mut resolver = Resolver {
+        source,
+        references: References::default(),
+        counters: HashMap::new(),
+        refs: Vec::new(),
+        errors: Vec::new(),
+    };
+    resolver.visit_exprs(exprs);
+
+    // References can point forward, so they're only checked at the end
+    let mut errors = resolver.errors;
+    for (range, label) in resolver.refs {
+        let error = if label.is_empty() {
+            ReferenceError::EmptyReference
+        } else if resolver.references.get(&label).is_none() {
+            ReferenceError::UndefinedLabel { label }
+        } else {
+            continue;
+        };
+        errors.push(Loc::new(range, error));
+    }
+    errors.sort_by_key(|error| error.range().start);
+
+    (resolver.references, errors)
+}
+
+struct Resolver<'a> {
+    source: &'a str,
+    references: References,
+    counters: HashMap<&'a str, usize>,
+    // The range and label of every reference
+    refs: Vec<(Span, String)>,
+    errors: Vec<Loc<ReferenceError>>,
+}
+
+impl<'a> Resolver<'a> {
+    fn number(&mut self, range: &Span, name: &'a str, label: Option<Span>) {
+        if label.is_none() && !NUMBERED.contains(&name) {
+            return;
+        }
+        let counter = self.counters.entry(name).or_insert(0);
+        *counter += 1;
+        let number = *counter;
+        self.references.numbers.insert(range.start, number);
+
+        let label_range = match label {
+            Some(label) => label,
+            None => return,
+        };
+        let label = unescape(&self.source[label_range.clone()]).into_owned();
+        if !is_valid_label(&label) {
+            self.errors.push(Loc::new(
+                label_range,
+                ReferenceError::InvalidLabel { label },
+            ));
+            return;
+        }
+        if let Some(first) = self.references.targets.get(&label) {
+            self.errors.push(Loc::new(
+                label_range,
+                ReferenceError::DuplicateLabel {
+                    label,
+                    first: first.range.clone(),
+                },
+            ));
+            return;
+        }
+        self.references.targets.insert(
+            label,
+            Target {
+                name: name.to_string(),
+                number,
+                range: label_range,
+            },
+        );
+    }
+}
+
+impl Visitor for Resolver<'_> {
+    fn visit_inline(&mut self, range: &Span, name: &Span, _args: &[Span], body: &[Loc<Expr>]) {
+        match reference_label(self.source, name, body) {
+            Some(label) => self.refs.push((range.clone(), label)),
+            None => self.visit_exprs(body),
+        }
+    }
+
+    fn visit_block(&mut self, range: &Span, name: &Span, args: &[Span], body: &[Loc<Expr>]) {
+        let name = &self.source[name.clone()];
+        self.number(range, name, label(self.source, args));
+        self.visit_exprs(body);
+    }
+
+    fn visit_math(&mut self, range: &Span, math: &Math) {
+        // Only `{equation|}` is numbered, like `equation` in LaTeX
+        if let (true, Some(name)) = (math.display, &math.name) {
+            let name = &self.source[name.clone()];
+            self.number(range, name, math.label.clone());
+        }
+    }
+}
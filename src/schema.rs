@@ -0,0 +1,537 @@
+//! Describes the elements a document may use and checks documents against
+//! them.
+//!
+//! The parser accepts any element name, so a misspelled `[bodl x]` parses
+//! fine and renders as an unknown element. `validate` walks a parsed
+//! document and reports elements that aren't in an `ElementRegistry`, are
+//! used as the wrong kind, have the wrong number of arguments or appear
+//! where they aren't allowed.
+//!
+//! ```
+//! use cayatex::schema::{self, ElementRegistry};
+//! use cayatex::Parser;
+//!
+//! let source = "{bold| x} [bodl y]";
+//! let exprs = Parser::new(source).parse_document().unwrap();
+//! let errors = schema::validate(source, &exprs, &ElementRegistry::standard());
+//! assert_eq!(errors.len(), 2);
+//! ```
+
+use crate::parser::{positional_args, ElementKind, Expr, Loc, Span};
+use crate::refs::REF;
+use std::collections::HashMap;
+use thiserror::Error;
+
+/// The elements known to a document, keyed by name and kind. The same name
+/// can be registered once as an inline and once as a block element.
+#[derive(Debug, Clone, Default)]
+pub struct ElementRegistry {
+    elements: HashMap<String, Vec<ElementSpec>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ElementSpec {
+    pub name: String,
+    pub kind: ElementKind,
+    /// The number of arguments, not counting a `label=` argument
+    pub min_args: usize,
+    /// `None` for any number of arguments
+    pub max_args: Option<usize>,
+    pub children: Children,
+    /// The elements this one may appear in, or `None` for anywhere
+    pub parents: Option<Vec<String>>,
+    /// Whether the body is kept verbatim, see `Parser::raw_elements`
+    pub raw: bool,
+}
+
+/// What the body of an element may contain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Children {
+    /// Text, inline and block elements
+    Any,
+    /// Text and inline elements
+    Inline,
+    /// Nothing, the element takes no body
+    Empty,
+    /// Only the listed elements, separated by whitespace
+    Only(Vec<String>),
+}
+
+#[derive(Error, Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ValidationError {
+    /// `suggestion` is the closest registered name of the same kind, if any
+    /// is close enough to be a likely misspelling. `fixable` is whether it's
+    /// certain enough to replace the name without review, see
+    /// `ElementRegistry::correct`.
+    #[error("unknown element `{}`{}", name, did_you_mean(suggestion))]
+    UnknownElement {
+        name: String,
+        suggestion: Option<String>,
+        fixable: bool,
+    },
+    /// An element that's only registered as the other kind, like `{bold|…}`
+    #[error("`{}` is {} element, not {}", name, with_article(*expected), with_article(*found))]
+    WrongKind {
+        name: String,
+        expected: ElementKind,
+        found: ElementKind,
+    },
+    #[error("`{}` takes {}, found {}", name, expected, found)]
+    WrongArgCount {
+        name: String,
+        expected: String,
+        found: usize,
+    },
+    /// A child that the body of its parent doesn't allow. `child` is an
+    /// element name, or `text` or `math`.
+    #[error("{} is not allowed inside `{}`", child, parent)]
+    DisallowedChild { child: String, parent: String },
+    /// An element outside of the elements it's restricted to
+    #[error("`{}` is only allowed inside {}", name, list_names(parents))]
+    MisplacedElement { name: String, parents: Vec<String> },
+}
+
+impl ElementRegistry {
+    /// An empty registry
+    pub fn new() -> Self {
+        ElementRegistry::default()
+    }
+
+    /// The elements understood by the `html` and `latex` renderers
+    pub fn standard() -> Self {
+        let mut registry = ElementRegistry::new();
+        for name in [
+            "bold",
+            "strong",
+            "italic",
+            "emph",
+            "underline",
+            "strike",
+            "sub",
+            "sup",
+        ] {
+            registry.register(ElementSpec::inline(name));
+        }
+        registry.register(ElementSpec::inline("code").with_raw_body());
+        registry.register(ElementSpec::inline("verbatim").with_raw_body());
+        registry.register(ElementSpec::inline("link").with_args(1, Some(1)));
+        registry.register(ElementSpec::inline("image").with_args(1, Some(1)));
+        registry.register(ElementSpec::inline("math").with_raw_body());
+        registry.register(ElementSpec::inline(REF));
+
+        registry.register(ElementSpec::block("paragraph").with_children(Children::Inline));
+        registry.register(ElementSpec::block("title").with_children(Children::Inline));
+        registry.register(ElementSpec::block("heading").with_children(Children::Inline));
+        registry.register(ElementSpec::block("section").with_args(0, Some(1)));
+        registry.register(ElementSpec::block("quote"));
+        registry.register(
+            ElementSpec::block("code")
+                .with_args(0, Some(1))
+                .with_raw_body(),
+        );
+        registry.register(ElementSpec::block("verbatim").with_raw_body());
+        for name in ["list", "enumerate"] {
+            registry.register(
+                ElementSpec::block(name).with_children(Children::Only(vec!["item".to_string()])),
+            );
+        }
+        registry.register(ElementSpec::block("item").with_parents(&["list", "enumerate"]));
+        for name in [
+            "theorem",
+            "lemma",
+            "corollary",
+            "definition",
+            "proof",
+            "example",
+        ] {
+            registry.register(ElementSpec::block(name));
+        }
+        registry.register(ElementSpec::block("equation").with_raw_body());
+
+        registry
+    }
+
+    /// Adds an element, replacing any element of the same name and kind
+    pub fn register(&mut self, spec: ElementSpec) {
+        let specs = self.elements.entry(spec.name.clone()).or_default();
+        specs.retain(|other| other.kind != spec.kind);
+        specs.push(spec);
+    }
+
+    pub fn get(&self, name: &str, kind: ElementKind) -> Option<&ElementSpec> {
+        self.elements
+            .get(name)?
+            .iter()
+            .find(|spec| spec.kind == kind)
+    }
+
+    pub fn specs(&self) -> impl Iterator<Item = &ElementSpec> {
+        self.elements.values().flatten()
+    }
+
+    /// The registered name of the given kind closest to `name`, if it's
+    /// within a third of the length of `name` in edit distance. Ties go to
+    /// the alphabetically first name.
+    ///
+    /// ```
+    /// use cayatex::schema::ElementRegistry;
+    /// use cayatex::ElementKind;
+    ///
+    /// let registry = ElementRegistry::standard();
+    /// assert_eq!(registry.suggest("itlaic", ElementKind::Inline), Some("italic"));
+    /// assert_eq!(registry.suggest("theorme", ElementKind::Block), Some("theorem"));
+    /// assert_eq!(registry.suggest("qwerty", ElementKind::Inline), None);
+    /// ```
+    pub fn suggest(&self, name: &str, kind: ElementKind) -> Option<&str> {
+        self.candidates(name, kind).first().map(|(_, name)| *name)
+    }
+
+    /// The suggestion for `name` when it's safe to apply without review:
+    /// `name` is at least four characters long, so that a single edit can't
+    /// turn one short name into another, no other name is as close, and the
+    /// suggestion isn't `ref`, which would turn the element into a reference.
+    ///
+    /// ```
+    /// use cayatex::schema::ElementRegistry;
+    /// use cayatex::ElementKind;
+    ///
+    /// let registry = ElementRegistry::standard();
+    /// assert_eq!(registry.correct("itlaic", ElementKind::Inline), Some("italic"));
+    /// assert_eq!(registry.correct("red", ElementKind::Inline), None);
+    /// ```
+    pub fn correct(&self, name: &str, kind: ElementKind) -> Option<&str> {
+        const MIN_LEN: usize = 4;
+        if name.chars().count() < MIN_LEN {
+            return None;
+        }
+        match self.candidates(name, kind).as_slice() {
+            [(_, closest)] if *closest != REF => Some(closest),
+            [(distance, closest), (next, _), ..] if distance < next && *closest != REF => {
+                Some(closest)
+            }
+            _ => None,
+        }
+    }
+
+    // Names of the given kind close enough to `name` to suggest, closest
+    // first
+    fn candidates(&self, name: &str, kind: ElementKind) -> Vec<(usize, &str)> {
+        let max_distance = (name.chars().count() / 3).max(1);
+        let mut candidates: Vec<_> = self
+            .specs()
+            .filter(|spec| spec.kind == kind)
+            .map(|spec| (edit_distance(name, &spec.name), spec.name.as_str()))
+            .filter(|(distance, _)| *distance <= max_distance)
+            .collect();
+        candidates.sort();
+        candidates
+    }
+
+    /// The names of the elements with raw bodies, to configure a `Parser`
+    /// with
+    pub fn raw_elements(&self) -> impl Iterator<Item = &str> {
+        self.specs()
+            .filter(|spec| spec.raw)
+            .map(|spec| spec.name.as_str())
+    }
+}
+
+impl ElementSpec {
+    /// An inline element without arguments whose body is text and inline
+    /// elements
+    pub fn inline<T: Into<String>>(name: T) -> Self {
+        ElementSpec {
+            name: name.into(),
+            kind: ElementKind::Inline,
+            min_args: 0,
+            max_args: Some(0),
+            children: Children::Inline,
+            parents: None,
+            raw: false,
+        }
+    }
+
+    /// A block element without arguments whose body may contain anything
+    pub fn block<T: Into<String>>(name: T) -> Self {
+        ElementSpec {
+            kind: ElementKind::Block,
+            children: Children::Any,
+            ..ElementSpec::inline(name)
+        }
+    }
+
+    pub fn with_args(mut self, min_args: usize, max_args: Option<usize>) -> Self {
+        self.min_args = min_args;
+        self.max_args = max_args;
+        self
+    }
+
+    pub fn with_children(mut self, children: Children) -> Self {
+        self.children = children;
+        self
+    }
+
+    pub fn with_parents(mut self, parents: &[&str]) -> Self {
+        self.parents = Some(parents.iter().map(|name| name.to_string()).collect());
+        self
+    }
+
+    pub fn with_raw_body(mut self) -> Self {
+        self.raw = true;
+        self
+    }
+
+    // A description of the accepted number of arguments, for errors
+    fn expected_args(&self) -> String {
+        let arguments = |count: usize| {
+            let plural = if count == 1 { "" } else { "s" };
+            format!("{} argument{}", count, plural)
+        };
+        match (self.min_args, self.max_args) {
+            (0, Some(0)) => "no arguments".to_string(),
+            (min, Some(max)) if min == max => arguments(min),
+            (0, Some(max)) => format!("at most {}", arguments(max)),
+            (min, Some(max)) => format!("{} to {} arguments", min, max),
+            (min, None) => format!("at least {}", arguments(min)),
+        }
+    }
+}
+
+/// Checks a document against `registry`. The errors are located at the
+/// name of the offending element, or at its arguments for a wrong number of
+/// arguments.
+pub fn validate(
+    source: &str,
+    exprs: &[Loc<Expr>],
+    registry: &ElementRegistry,
+) -> Vec<Loc<ValidationError>> {
+    let mut validator = Validator {
+        source,
+        registry,
+        errors: Vec::new(),
+    };
+    validator.validate_exprs(exprs, None);
+    validator.errors
+}
+
+struct Validator<'a> {
+    source: &'a str,
+    registry: &'a ElementRegistry,
+    errors: Vec<Loc<ValidationError>>,
+}
+
+// The known element whose body is being validated
+struct Parent<'a> {
+    name: &'a str,
+    children: &'a Children,
+}
+
+impl<'a> Validator<'a> {
+    fn validate_exprs(&mut self, exprs: &[Loc<Expr>], parent: Option<&Parent>) {
+        for expr in exprs {
+            match expr.inner() {
+                Expr::Inline { name, args, body } => {
+                    self.validate_element(ElementKind::Inline, name, args, Some(body), parent)
+                }
+                Expr::Block { name, args, body } => {
+                    self.validate_element(ElementKind::Block, name, args, Some(body), parent)
+                }
+                Expr::Math(math) => match &math.name {
+                    Some(name) => {
+                        let kind = if math.display {
+                            ElementKind::Block
+                        } else {
+                            ElementKind::Inline
+                        };
+                        self.validate_element(kind, name, &math.args, None, parent);
+                    }
+                    None => self.validate_child(expr.range(), None, "math", None, parent),
+                },
+                Expr::Text(span) => {
+                    let text = &self.source[span.clone()];
+                    let trimmed = text.trim();
+                    if !trimmed.is_empty() {
+                        let start = span.start + (text.len() - text.trim_start().len());
+                        let range = start..(start + trimmed.len());
+                        self.validate_child(range, None, "text", None, parent);
+                    }
+                }
+                Expr::Raw(_) | Expr::Error => {}
+            }
+        }
+    }
+
+    // `body` is `None` for math elements, whose TeX isn't checked
+    fn validate_element(
+        &mut self,
+        kind: ElementKind,
+        name: &Span,
+        args: &[Span],
+        body: Option<&[Loc<Expr>]>,
+        parent: Option<&Parent>,
+    ) {
+        let name_str = &self.source[name.clone()];
+        let spec = match self.registry.get(name_str, kind) {
+            Some(spec) => spec,
+            None => {
+                let other_kind = match kind {
+                    ElementKind::Inline => ElementKind::Block,
+                    ElementKind::Block => ElementKind::Inline,
+                };
+                let error = if self.registry.get(name_str, other_kind).is_some() {
+                    ValidationError::WrongKind {
+                        name: name_str.to_string(),
+                        expected: other_kind,
+                        found: kind,
+                    }
+                } else {
+                    ValidationError::UnknownElement {
+                        name: name_str.to_string(),
+                        suggestion: self.registry.suggest(name_str, kind).map(str::to_string),
+                        fixable: self.registry.correct(name_str, kind).is_some(),
+                    }
+                };
+                self.errors.push(Loc::new(name.clone(), error));
+                // The body of an unknown element is still worth checking
+                if let Some(body) = body {
+                    self.validate_exprs(body, None);
+                }
+                return;
+            }
+        };
+
+        let description = match kind {
+            ElementKind::Inline => format!("`{}`", name_str),
+            ElementKind::Block => format!("block `{}`", name_str),
+        };
+        self.validate_child(
+            name.clone(),
+            Some(name_str),
+            &description,
+            Some(kind),
+            parent,
+        );
+        if let Some(parents) = &spec.parents {
+            if !parent.is_some_and(|parent| parents.iter().any(|name| name == parent.name)) {
+                self.errors.push(Loc::new(
+                    name.clone(),
+                    ValidationError::MisplacedElement {
+                        name: name_str.to_string(),
+                        parents: parents.clone(),
+                    },
+                ));
+            }
+        }
+
+        let positional = positional_args(self.source, args);
+        let count = positional.len();
+        if count < spec.min_args || spec.max_args.is_some_and(|max| count > max) {
+            let range = match (positional.first(), positional.last()) {
+                (Some(first), Some(last)) => first.start..last.end,
+                _ => name.clone(),
+            };
+            self.errors.push(Loc::new(
+                range,
+                ValidationError::WrongArgCount {
+                    name: name_str.to_string(),
+                    expected: spec.expected_args(),
+                    found: count,
+                },
+            ));
+        }
+
+        if let Some(body) = body {
+            let parent = Parent {
+                name: &spec.name,
+                children: &spec.children,
+            };
+            self.validate_exprs(body, Some(&parent));
+        }
+    }
+
+    // Checks that the body of `parent` may contain a child. `name` and `kind`
+    // are `None` for text and math written with `$`, which count as inline
+    // content.
+    fn validate_child(
+        &mut self,
+        range: Span,
+        name: Option<&str>,
+        description: &str,
+        kind: Option<ElementKind>,
+        parent: Option<&Parent>,
+    ) {
+        let parent = match parent {
+            Some(parent) => parent,
+            None => return,
+        };
+        let allowed = match parent.children {
+            Children::Any => true,
+            Children::Inline => kind != Some(ElementKind::Block),
+            Children::Empty => false,
+            Children::Only(names) => {
+                name.is_some_and(|name| names.iter().any(|other| other == name))
+            }
+        };
+        if !allowed {
+            self.errors.push(Loc::new(
+                range,
+                ValidationError::DisallowedChild {
+                    child: description.to_string(),
+                    parent: parent.name.to_string(),
+                },
+            ));
+        }
+    }
+}
+
+fn did_you_mean(suggestion: &Option<String>) -> String {
+    match suggestion {
+        Some(suggestion) => format!(", did you mean `{}`?", suggestion),
+        None => String::new(),
+    }
+}
+
+// The optimal string alignment distance: the number of insertions,
+// deletions, substitutions and transpositions of adjacent characters that
+// turn one string into the other, so `itlaic` is one edit from `italic`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    // Three rows of the distance matrix, since transpositions look two rows
+    // back
+    let mut before_previous = vec![0; b.len() + 1];
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for i in 1..=a.len() {
+        current[0] = i;
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            current[j] = (previous[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before_previous[j - 2] + 1);
+            }
+        }
+        std::mem::swap(&mut before_previous, &mut previous);
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+fn with_article(kind: ElementKind) -> &'static str {
+    match kind {
+        ElementKind::Inline => "an inline",
+        ElementKind::Block => "a block",
+    }
+}
+
+fn list_names(names: &[String]) -> String {
+    let names: Vec<String> = names.iter().map(|name| format!("`{}`", name)).collect();
+    match names.split_last() {
+        Some((last, [])) => last.clone(),
+        Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+        None => String::new(),
+    }
+}
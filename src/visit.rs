@@ -0,0 +1,189 @@
+//! Traits for walking and rewriting `Expr` trees.
+//!
+//! Each method has a default implementation that recurses into element
+//! bodies, so an implementation only needs to override the nodes it cares
+//! about. To keep recursing from an overridden element method, visit or
+//! fold the body.
+//!
+//! ```
+//! use cayatex::visit::Visitor;
+//! use cayatex::{Expr, Loc, Parser, Span};
+//!
+//! struct CountInline(usize);
+//!
+//! impl Visitor for CountInline {
+//!     fn visit_inline(&mut self, _: &Span, _: &Span, _: &[Span], body: &[Loc<Expr>]) {
+//!         self.0 += 1;
+//!         self.visit_exprs(body);
+//!     }
+//! }
+//!
+//! let exprs = Parser::new("[bold a [italic b]] {quote| [code c]}")
+//!     .parse_document()
+//!     .unwrap();
+//! let mut counter = CountInline(0);
+//! counter.visit_exprs(&exprs);
+//! assert_eq!(counter.0, 3);
+//! ```
+
+use crate::parser::{Expr, Loc, Math, Span};
+
+/// Walks a tree by reference.
+pub trait Visitor {
+    fn visit_exprs(&mut self, exprs: &[Loc<Expr>]) {
+        walk_exprs(self, exprs);
+    }
+
+    fn visit_expr(&mut self, expr: &Loc<Expr>) {
+        walk_expr(self, expr);
+    }
+
+    fn visit_inline(&mut self, _range: &Span, _name: &Span, _args: &[Span], body: &[Loc<Expr>]) {
+        self.visit_exprs(body);
+    }
+
+    fn visit_block(&mut self, _range: &Span, _name: &Span, _args: &[Span], body: &[Loc<Expr>]) {
+        self.visit_exprs(body);
+    }
+
+    fn visit_text(&mut self, _span: &Span) {}
+
+    fn visit_raw(&mut self, _span: &Span) {}
+
+    fn visit_math(&mut self, _range: &Span, _math: &Math) {}
+
+    fn visit_error(&mut self, _range: &Span) {}
+}
+
+pub fn walk_exprs<V: Visitor + ?Sized>(visitor: &mut V, exprs: &[Loc<Expr>]) {
+    for expr in exprs {
+        visitor.visit_expr(expr);
+    }
+}
+
+pub fn walk_expr<V: Visitor + ?Sized>(visitor: &mut V, expr: &Loc<Expr>) {
+    let range = expr.range();
+    match expr.inner() {
+        Expr::Inline { name, args, body } => visitor.visit_inline(&range, name, args, body),
+        Expr::Block { name, args, body } => visitor.visit_block(&range, name, args, body),
+        Expr::Text(span) => visitor.visit_text(span),
+        Expr::Raw(span) => visitor.visit_raw(span),
+        Expr::Math(math) => visitor.visit_math(&range, math),
+        Expr::Error => visitor.visit_error(&range),
+    }
+}
+
+/// Walks a tree by mutable reference, for rewriting it in place.
+pub trait VisitorMut {
+    fn visit_exprs_mut(&mut self, exprs: &mut Vec<Loc<Expr>>) {
+        walk_exprs_mut(self, exprs);
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Loc<Expr>) {
+        walk_expr_mut(self, expr);
+    }
+
+    fn visit_inline_mut(
+        &mut self,
+        _name: &mut Span,
+        _args: &mut Vec<Span>,
+        body: &mut Vec<Loc<Expr>>,
+    ) {
+        self.visit_exprs_mut(body);
+    }
+
+    fn visit_block_mut(
+        &mut self,
+        _name: &mut Span,
+        _args: &mut Vec<Span>,
+        body: &mut Vec<Loc<Expr>>,
+    ) {
+        self.visit_exprs_mut(body);
+    }
+
+    fn visit_text_mut(&mut self, _span: &mut Span) {}
+
+    fn visit_raw_mut(&mut self, _span: &mut Span) {}
+
+    fn visit_math_mut(&mut self, _math: &mut Math) {}
+}
+
+pub fn walk_exprs_mut<V: VisitorMut + ?Sized>(visitor: &mut V, exprs: &mut Vec<Loc<Expr>>) {
+    for expr in exprs {
+        visitor.visit_expr_mut(expr);
+    }
+}
+
+pub fn walk_expr_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expr: &mut Loc<Expr>) {
+    match expr.inner_mut() {
+        Expr::Inline { name, args, body } => visitor.visit_inline_mut(name, args, body),
+        Expr::Block { name, args, body } => visitor.visit_block_mut(name, args, body),
+        Expr::Text(span) => visitor.visit_text_mut(span),
+        Expr::Raw(span) => visitor.visit_raw_mut(span),
+        Expr::Math(math) => visitor.visit_math_mut(math),
+        Expr::Error => {}
+    }
+}
+
+/// Rewrites a tree by taking it apart and building a new one. Unlike
+/// `VisitorMut` a node can be replaced by a different kind of node, and
+/// `fold_exprs` can drop nodes or expand one node into several.
+pub trait Fold {
+    fn fold_exprs(&mut self, exprs: Vec<Loc<Expr>>) -> Vec<Loc<Expr>> {
+        exprs.into_iter().map(|expr| self.fold_expr(expr)).collect()
+    }
+
+    fn fold_expr(&mut self, expr: Loc<Expr>) -> Loc<Expr> {
+        fold_expr(self, expr)
+    }
+
+    fn fold_inline(
+        &mut self,
+        range: Span,
+        name: Span,
+        args: Vec<Span>,
+        body: Vec<Loc<Expr>>,
+    ) -> Loc<Expr> {
+        let body = self.fold_exprs(body);
+        Loc::new(range, Expr::Inline { name, args, body })
+    }
+
+    fn fold_block(
+        &mut self,
+        range: Span,
+        name: Span,
+        args: Vec<Span>,
+        body: Vec<Loc<Expr>>,
+    ) -> Loc<Expr> {
+        let body = self.fold_exprs(body);
+        Loc::new(range, Expr::Block { name, args, body })
+    }
+
+    fn fold_text(&mut self, range: Span, span: Span) -> Loc<Expr> {
+        Loc::new(range, Expr::Text(span))
+    }
+
+    fn fold_raw(&mut self, range: Span, span: Span) -> Loc<Expr> {
+        Loc::new(range, Expr::Raw(span))
+    }
+
+    fn fold_math(&mut self, range: Span, math: Math) -> Loc<Expr> {
+        Loc::new(range, Expr::Math(math))
+    }
+
+    fn fold_error(&mut self, range: Span) -> Loc<Expr> {
+        Loc::new(range, Expr::Error)
+    }
+}
+
+pub fn fold_expr<F: Fold + ?Sized>(folder: &mut F, expr: Loc<Expr>) -> Loc<Expr> {
+    let range = expr.range();
+    match expr.into_inner() {
+        Expr::Inline { name, args, body } => folder.fold_inline(range, name, args, body),
+        Expr::Block { name, args, body } => folder.fold_block(range, name, args, body),
+        Expr::Text(span) => folder.fold_text(range, span),
+        Expr::Raw(span) => folder.fold_raw(range, span),
+        Expr::Math(math) => folder.fold_math(range, math),
+        Expr::Error => folder.fold_error(range),
+    }
+}
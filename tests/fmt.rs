@@ -0,0 +1,141 @@
+use cayatex::fmt::{self, FormatOptions};
+use cayatex::{unescape, Expr, Loc, Parser};
+
+const DOCUMENTS: &[&str] = &[
+    "Read [bold this ]now.",
+    "Read [bold this ] now and [italic [bold nested ]]then.",
+    "[bold x\\ ]now",
+    "{quote \\[a\\ | x}",
+    "[bold \\[a\\ | x]",
+    "[link \"https://example.com/a b\" | a link] and [code x  y]",
+    "{section \"A title\" label=intro|\n  Some text.\n\n  More [emph text].\n}",
+    "{theorem| $x^2$ and $$y$$}\n\n{equation label=e| a + b}",
+    "日本語のテキスト [bold 太字 ]です。",
+    "{theorem|\n{code rust|\n  fn main() {}\n    }\n{equation label=e|\n  x^2\n}}",
+    "{list| {item| {verbatim| a\n b}}}",
+];
+
+#[test]
+fn format_is_idempotent() {
+    for width in [1, 12, 80] {
+        let options = FormatOptions {
+            width,
+            ..FormatOptions::default()
+        };
+        for source in DOCUMENTS {
+            let formatted = format(source, &options);
+            assert_eq!(format(&formatted, &options), formatted, "{:?}", source);
+        }
+    }
+}
+
+#[test]
+fn format_keeps_the_tree() {
+    for width in [1, 12, 80] {
+        let options = FormatOptions {
+            width,
+            ..FormatOptions::default()
+        };
+        for source in DOCUMENTS {
+            let formatted = format(source, &options);
+            assert_eq!(
+                shape(&formatted),
+                shape(source),
+                "{:?} formatted as {:?}",
+                source,
+                formatted
+            );
+        }
+    }
+}
+
+fn format(source: &str, options: &FormatOptions) -> String {
+    let exprs = Parser::new(source)
+        .parse_document()
+        .unwrap_or_else(|err| panic!("{:?} doesn't parse: {:?}", source, err));
+    fmt::format(source, &exprs, options)
+}
+
+// Elements are marked with control characters in shapes so they can't be
+// confused with text
+const INLINE_START: char = '\u{1}';
+const INLINE_END: char = '\u{2}';
+const BLOCK_START: char = '\u{3}';
+const BLOCK_END: char = '\u{4}';
+
+// The document with whitespace normalised the way the formatter is allowed
+// to change it: runs of whitespace are one space, and whitespace at the end
+// of an inline element's body counts as being after the element
+fn shape(source: &str) -> String {
+    let exprs = Parser::new(source)
+        .parse_document()
+        .unwrap_or_else(|err| panic!("{:?} doesn't parse: {:?}", source, err));
+    let mut out = String::new();
+    push_shape(source, &exprs, &mut out);
+
+    let mut shape = String::new();
+    let mut space = false;
+    for c in out.chars() {
+        match c {
+            _ if c.is_whitespace() => space = true,
+            // Whitespace around blocks isn't significant
+            BLOCK_START | BLOCK_END => {
+                space = false;
+                shape.push(c);
+            }
+            INLINE_END => shape.push(c),
+            _ => {
+                if space && !shape.is_empty() && !shape.ends_with([BLOCK_START, BLOCK_END]) {
+                    shape.push(' ');
+                }
+                space = false;
+                shape.push(c);
+            }
+        }
+    }
+    shape
+}
+
+fn push_shape(source: &str, exprs: &[Loc<Expr>], out: &mut String) {
+    for expr in exprs {
+        match expr.inner() {
+            Expr::Inline { name, args, body } | Expr::Block { name, args, body } => {
+                let block = matches!(expr.inner(), Expr::Block { .. });
+                out.push(if block { BLOCK_START } else { INLINE_START });
+                out.push_str(&source[name.clone()]);
+                for arg in args {
+                    out.push_str(&format!("({:?})", unescape(&source[arg.clone()])));
+                }
+                out.push('|');
+                push_shape(source, body, out);
+                out.push(if block { BLOCK_END } else { INLINE_END });
+            }
+            Expr::Text(span) => out.push_str(&unescape(&source[span.clone()])),
+            Expr::Raw(span) => {
+                let text = raw_text(&source[span.clone()]);
+                if !text.is_empty() {
+                    out.push_str(&format!("<{:?}>", text));
+                }
+            }
+            // Display math is laid out like a block
+            Expr::Math(math) if math.display => {
+                let tex = raw_text(&source[math.tex.clone()]);
+                out.push_str(&format!("{}<{:?}>{}", BLOCK_START, tex, BLOCK_END));
+            }
+            Expr::Math(math) => {
+                out.push_str(&format!("<{:?}>", raw_text(&source[math.tex.clone()])))
+            }
+            Expr::Error => panic!("error in {:?}", source),
+        }
+    }
+}
+
+// The whitespace before the closing bracket of a raw block is its indentation
+// rather than part of the body
+fn raw_text(text: &str) -> &str {
+    match text.rfind('\n') {
+        Some(idx) if text[(idx + 1)..].trim().is_empty() => &text[..=idx],
+        None if text.trim().is_empty() => "",
+        _ => text,
+    }
+}